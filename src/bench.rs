@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+pub const DEFAULT_DIR: &str = "benchmarks";
+const BASELINE_FILE: &str = "baseline.txt";
+const RUNS: usize = 10;
+/// How much slower than its baseline a benchmark may get before it counts as a regression
+const REGRESSION_THRESHOLD: f64 = 0.10;
+/// A slowdown also has to be this many standard deviations of the noisier of
+/// the two measurements, so a jittery machine doesn't report regressions
+const NOISE_DEVIATIONS: f64 = 3.0;
+
+/// The fastest run of a benchmark and how much its runs varied
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Baseline {
+    min: f64,
+    stddev: f64,
+}
+
+struct Timing {
+    name: String,
+    mean: f64,
+    min: f64,
+    stddev: f64,
+}
+
+impl Timing {
+    fn new(name: String, samples: &[f64]) -> Timing {
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let min = samples.iter().cloned().fold(f64::INFINITY, f64::min);
+        let variance =
+            samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+        Timing {
+            name,
+            mean,
+            min,
+            stddev: variance.sqrt(),
+        }
+    }
+
+    /// The change from `baseline`, and whether it is a regression rather than noise
+    fn compare(&self, baseline: &Baseline) -> (f64, bool) {
+        let change = (self.min - baseline.min) / baseline.min;
+        let noise = NOISE_DEVIATIONS * self.stddev.max(baseline.stddev) / baseline.min;
+        (change, change > REGRESSION_THRESHOLD.max(noise))
+    }
+}
+
+/// Time every script in `dir` and compare each one's fastest run with the
+/// saved baseline. Returns the exit code: 1 if anything regressed.
+///
+/// Each run is a separate `rlox` process so scripts can't affect each other
+/// and their output can be thrown away.
+pub fn run(dir: &Path, save_baseline: bool) -> i32 {
+    let scripts = match scripts(dir) {
+        Ok(scripts) => scripts,
+        Err(message) => {
+            eprintln!("{}", message);
+            return 66;
+        }
+    };
+    let baseline_path = dir.join(BASELINE_FILE);
+    let baseline = fs::read_to_string(&baseline_path)
+        .map(|contents| parse_baseline(&contents))
+        .unwrap_or_default();
+
+    println!(
+        "{:<16} {:>9} {:>9} {:>9} {:>9} {:>8}",
+        "benchmark", "mean", "min", "stddev", "baseline", "change"
+    );
+    let mut timings = Vec::new();
+    let mut regressed = false;
+    for script in scripts {
+        let name = script.file_stem().unwrap().to_string_lossy().to_string();
+        let timing = match time_script(&script) {
+            Ok(samples) => Timing::new(name, &samples),
+            Err(message) => {
+                eprintln!("{}: {}", name, message);
+                return 70;
+            }
+        };
+        let comparison = match baseline.get(&timing.name) {
+            Some(base) => {
+                let (change, regression) = timing.compare(base);
+                let marker = if regression {
+                    regressed = true;
+                    "  regressed"
+                } else {
+                    ""
+                };
+                format!("{:>8.3}s {:>+7.1}%{}", base.min, change * 100.0, marker)
+            }
+            None => format!("{:>9} {:>8}", "-", "-"),
+        };
+        println!(
+            "{:<16} {:>8.3}s {:>8.3}s {:>8.3}s {}",
+            timing.name, timing.mean, timing.min, timing.stddev, comparison
+        );
+        timings.push(timing);
+    }
+
+    if save_baseline {
+        if let Err(error) = fs::write(&baseline_path, format_baseline(&timings)) {
+            eprintln!("Could not write {}: {}", baseline_path.display(), error);
+            return 74;
+        }
+        println!("Saved baseline to {}", baseline_path.display());
+    }
+    if regressed {
+        1
+    } else {
+        0
+    }
+}
+
+fn scripts(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = fs::read_dir(dir)
+        .map_err(|error| format!("Could not read {}: {}", dir.display(), error))?;
+    let mut scripts: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "rlox")
+        })
+        .collect();
+    scripts.sort();
+    Ok(scripts)
+}
+
+fn time_script(script: &Path) -> Result<Vec<f64>, String> {
+    let exe = env::current_exe().map_err(|error| error.to_string())?;
+    let mut samples = Vec::with_capacity(RUNS);
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let status = Command::new(&exe)
+            .arg(script)
+            .stdout(Stdio::null())
+            .status()
+            .map_err(|error| error.to_string())?;
+        if !status.success() {
+            return Err(format!("exited with {}", status));
+        }
+        samples.push(start.elapsed().as_secs_f64());
+    }
+    Ok(samples)
+}
+
+/// One `name seconds stddev` line per benchmark. Baselines saved before the
+/// standard deviation was recorded leave it out.
+fn parse_baseline(contents: &str) -> BTreeMap<String, Baseline> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let name = parts.next()?;
+            let min = parts.next()?.parse().ok()?;
+            let stddev = match parts.next() {
+                Some(stddev) => stddev.parse().ok()?,
+                None => 0.0,
+            };
+            Some((name.to_string(), Baseline { min, stddev }))
+        })
+        .collect()
+}
+
+fn format_baseline(timings: &[Timing]) -> String {
+    timings
+        .iter()
+        .map(|timing| format!("{} {:.6} {:.6}\n", timing.name, timing.min, timing.stddev))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_baseline_round_trip() {
+        let timings = vec![
+            Timing::new("fib".to_string(), &[0.25, 0.2, 0.3]),
+            Timing::new("loops".to_string(), &[1.5]),
+        ];
+        let baseline = parse_baseline(&format_baseline(&timings));
+        assert_eq!(baseline.len(), 2);
+        assert_eq!(baseline["fib"].min, 0.2);
+        assert!((baseline["fib"].stddev - 0.040825).abs() < 1e-6);
+        assert_eq!(
+            baseline["loops"],
+            Baseline {
+                min: 1.5,
+                stddev: 0.0
+            }
+        );
+        assert_eq!(parse_baseline("old 0.5\n")["old"].stddev, 0.0);
+    }
+
+    #[test]
+    fn test_regression_threshold() {
+        let steady = Baseline {
+            min: 1.0,
+            stddev: 0.01,
+        };
+        assert!(
+            !Timing::new("t".to_string(), &[1.05, 1.05])
+                .compare(&steady)
+                .1
+        );
+        assert!(Timing::new("t".to_string(), &[1.2, 1.2]).compare(&steady).1);
+        // The same slowdown is within the noise of a jittery baseline
+        let noisy = Baseline {
+            min: 1.0,
+            stddev: 0.1,
+        };
+        assert!(!Timing::new("t".to_string(), &[1.2, 1.2]).compare(&noisy).1);
+    }
+
+    #[test]
+    fn test_timing_statistics() {
+        let timing = Timing::new("t".to_string(), &[1.0, 3.0]);
+        assert_eq!(timing.mean, 2.0);
+        assert_eq!(timing.min, 1.0);
+        assert_eq!(timing.stddev, 1.0);
+    }
+}
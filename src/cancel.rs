@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Asks a running interpreter to stop. The interpreter checks it at loop
+/// back-edges and calls, and stops with `LangError::Interrupted`. Clones
+/// share the same flag and can be sent to other threads.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    // rlox itself only cancels from its SIGINT handler; this is for hosts
+    #[allow(dead_code)]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Forget a cancellation that hasn't been acted on yet
+    pub fn clear(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether the handle was cancelled, clearing it so that only the
+    /// current evaluation is stopped
+    pub fn take(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) && self.cancelled.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Cancel `handle` on Ctrl-C instead of letting it kill the process
+#[cfg(unix)]
+pub fn cancel_on_sigint(handle: &CancelHandle) {
+    use std::os::raw::c_int;
+    use std::sync::atomic::AtomicPtr;
+
+    static FLAG: AtomicPtr<AtomicBool> = AtomicPtr::new(std::ptr::null_mut());
+
+    // Only atomic operations are safe in a signal handler
+    extern "C" fn on_sigint(_: c_int) {
+        let flag = FLAG.load(Ordering::Relaxed);
+        if !flag.is_null() {
+            unsafe { (*flag).store(true, Ordering::Relaxed) };
+        }
+    }
+
+    // The handler may run at any point from now on, so the flag it points
+    // to is leaked rather than ever freed
+    let flag = Arc::into_raw(handle.cancelled.clone()) as *mut AtomicBool;
+    FLAG.store(flag, Ordering::Relaxed);
+    let handler: extern "C" fn(c_int) = on_sigint;
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn cancel_on_sigint(_handle: &CancelHandle) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_handle_is_shared_between_threads() {
+        let handle = CancelHandle::default();
+        let remote = handle.clone();
+        std::thread::spawn(move || remote.cancel()).join().unwrap();
+        assert!(handle.take());
+        assert!(!handle.take());
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::process::exit;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::expr::Expr;
+use crate::interpreter::Interpreter;
+use crate::lang_error::LangError;
+use crate::object::Object;
+use crate::parser::Parser;
+use crate::scanner::scanner::Scanner;
+use crate::scanner::token::{Token, TokenType};
+
+const HELP: &str = "\
+step (s)            run to the next statement
+next (n)            run to the next statement in this function or its callers
+finish (f)          run until this function returns
+continue (c)        run to the next breakpoint
+break (b) <line>    stop before running line
+delete (d) <line>   remove the breakpoint on line
+breakpoints         list the breakpoints
+list                show the code around the current line
+locals (l)          show the local variables
+globals (g)         show the global variables
+print (p) <expr>    evaluate an expression where the script stopped
+quit (q)            stop the script";
+
+/// How far to run before stopping again
+#[derive(Clone, Copy)]
+enum Mode {
+    Step,
+    /// Stop at a statement at most this many calls deep
+    Next(usize),
+    /// Stop once the call at this depth has returned
+    Finish(usize),
+    /// Stop only at breakpoints
+    Continue,
+}
+
+/// Stops the interpreter before statements and takes commands from stdin
+#[derive(Clone)]
+pub struct Debugger {
+    source: Vec<String>,
+    breakpoints: BTreeSet<u32>,
+    mode: Mode,
+    /// Line and call depth of the last statement, so several statements on
+    /// one line only stop once
+    last: Option<(u32, usize)>,
+}
+
+impl Debugger {
+    pub fn new(source: &str) -> Debugger {
+        Debugger {
+            source: source.lines().map(|line| line.to_string()).collect(),
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+            last: None,
+        }
+    }
+
+    pub fn before_statement(&mut self, interpreter: &mut Interpreter, line: u32) {
+        if self.should_stop(line, interpreter.call_depth()) {
+            self.show_line(line, "=>");
+            self.prompt(interpreter, line);
+        }
+    }
+
+    /// Whether to stop before a statement on `line`, `depth` calls deep
+    fn should_stop(&mut self, line: u32, depth: usize) -> bool {
+        if self.last == Some((line, depth)) {
+            return false;
+        }
+        self.last = Some((line, depth));
+        self.breakpoints.contains(&line)
+            || match self.mode {
+                Mode::Step => true,
+                Mode::Next(next_depth) => depth <= next_depth,
+                Mode::Finish(finish_depth) => depth < finish_depth,
+                Mode::Continue => false,
+            }
+    }
+
+    /// The call at `depth` has been replaced by a tail call, which returns
+    /// from it as far as `finish` is concerned
+    pub fn tail_call(&mut self, depth: usize) {
+        self.last = None;
+        if let Mode::Finish(finish_depth) = self.mode {
+            if depth <= finish_depth {
+                self.mode = Mode::Step;
+            }
+        }
+    }
+
+    fn prompt(&mut self, interpreter: &mut Interpreter, line: u32) {
+        loop {
+            print!("(debug) ");
+            io::stdout().flush().unwrap();
+            let mut input = String::new();
+            if io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+                // Nobody is left to give commands, so let the script finish
+                self.breakpoints.clear();
+                self.mode = Mode::Continue;
+                return;
+            }
+            let input = input.trim();
+            let (command, argument) = match input.split_once(' ') {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (input, ""),
+            };
+            if let "q" | "quit" = command {
+                exit(0);
+            }
+            match resume_mode(command, interpreter.call_depth()) {
+                Some(mode) => {
+                    self.mode = mode;
+                    return;
+                }
+                None => self.run_command(interpreter, command, argument, line),
+            }
+        }
+    }
+
+    /// Carry out a command that doesn't resume the script
+    fn run_command(
+        &mut self,
+        interpreter: &mut Interpreter,
+        command: &str,
+        argument: &str,
+        line: u32,
+    ) {
+        match command {
+            "b" | "break" => match argument.parse() {
+                Ok(line) if line > 0 => {
+                    self.breakpoints.insert(line);
+                }
+                _ => println!("Usage: break <line>"),
+            },
+            "d" | "delete" => match argument.parse() {
+                Ok(line) if self.breakpoints.remove(&line) => (),
+                _ => println!("No breakpoint on line '{}'.", argument),
+            },
+            "breakpoints" => {
+                for &breakpoint in self.breakpoints.iter() {
+                    self.show_line(breakpoint, "*");
+                }
+            }
+            "list" => {
+                let first = line.saturating_sub(3).max(1);
+                for number in first..=line + 3 {
+                    let marker = if number == line { "=>" } else { "" };
+                    self.show_line(number, marker);
+                }
+            }
+            "l" | "locals" => print_locals(interpreter),
+            "g" | "globals" => print_globals(interpreter),
+            "p" | "print" => print_expression(interpreter, argument, line),
+            "h" | "help" => println!("{}", HELP),
+            "" => (),
+            _ => println!("Unknown command '{}'. Try 'help'.", command),
+        }
+    }
+
+    fn show_line(&self, line: u32, marker: &str) {
+        let index = (line as usize).checked_sub(1);
+        if let Some(text) = index.and_then(|index| self.source.get(index)) {
+            println!("{:>2} {:>4} | {}", marker, line, text);
+        }
+    }
+}
+
+/// How far a command resumes the script, or `None` if it doesn't
+fn resume_mode(command: &str, depth: usize) -> Option<Mode> {
+    match command {
+        "s" | "step" => Some(Mode::Step),
+        "n" | "next" => Some(Mode::Next(depth)),
+        "f" | "finish" => Some(Mode::Finish(depth)),
+        "c" | "continue" => Some(Mode::Continue),
+        _ => None,
+    }
+}
+
+/// The innermost local called `name`, as `(depth, slot)`
+fn find_local(environment: &Rc<Environment>, name: &str) -> Option<(usize, usize)> {
+    let mut environment = Some(environment);
+    let mut depth = 0;
+    while let Some(env) = environment {
+        if let Some(slot) = env.slot_of(name) {
+            return Some((depth, slot));
+        }
+        environment = env.enclosing.as_ref();
+        depth += 1;
+    }
+    None
+}
+
+/// Point the variables in an expression typed at the prompt at the locals
+/// they name, since the resolver never saw the frame it runs in
+fn resolve_names(expr: &Expr, interpreter: &mut Interpreter) {
+    match expr {
+        Expr::Assign(expr) => {
+            resolve_names(&expr.value, interpreter);
+            resolve_name(expr.id, &expr.name.lexeme, interpreter);
+        }
+        Expr::Binary(expr) => {
+            resolve_names(&expr.left, interpreter);
+            resolve_names(&expr.right, interpreter);
+        }
+        Expr::Call(expr) => {
+            resolve_names(&expr.callee, interpreter);
+            for argument in expr.arguments.iter() {
+                resolve_names(argument, interpreter);
+            }
+        }
+        Expr::Conditional(expr) => {
+            resolve_names(&expr.condition, interpreter);
+            resolve_names(&expr.then_branch, interpreter);
+            resolve_names(&expr.else_branch, interpreter);
+        }
+        Expr::Get(expr) => resolve_names(&expr.object, interpreter),
+        Expr::Grouping(expr) => resolve_names(&expr.expression, interpreter),
+        Expr::Index(expr) => {
+            resolve_names(&expr.object, interpreter);
+            resolve_names(&expr.index, interpreter);
+        }
+        Expr::Lambda(_) | Expr::Literal(_) => (),
+        Expr::Logical(expr) => {
+            resolve_names(&expr.left, interpreter);
+            resolve_names(&expr.right, interpreter);
+        }
+        Expr::Range(expr) => {
+            resolve_names(&expr.start, interpreter);
+            resolve_names(&expr.end, interpreter);
+        }
+        Expr::Set(expr) => {
+            resolve_names(&expr.object, interpreter);
+            resolve_names(&expr.value, interpreter);
+        }
+        Expr::Super(expr) => resolve_name(expr.id, &expr.keyword.lexeme, interpreter),
+        Expr::This(expr) => resolve_name(expr.id, &expr.keyword.lexeme, interpreter),
+        Expr::Tuple(expr) => {
+            for element in expr.elements.iter() {
+                resolve_names(element, interpreter);
+            }
+        }
+        Expr::Unary(expr) => resolve_names(&expr.right, interpreter),
+        Expr::Variable(expr) => resolve_name(expr.id, &expr.name.lexeme, interpreter),
+    }
+}
+
+fn resolve_name(id: u64, name: &str, interpreter: &mut Interpreter) {
+    if let Some((depth, slot)) = find_local(&interpreter.environment, name) {
+        interpreter.resolve(id, depth, slot);
+    }
+}
+
+/// Evaluate an expression typed at the prompt in the frame the script stopped in
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<Object, LangError> {
+    let tokens = Scanner::new(source.to_string()).scan_tokens();
+    let expr = Parser::new(tokens).parse_expression()?;
+    resolve_names(&expr, interpreter);
+    let environment = interpreter.environment.clone();
+    interpreter.evaluate_in(&expr, environment)
+}
+
+fn print_expression(interpreter: &mut Interpreter, source: &str, line: u32) {
+    // The parser reports its own errors
+    match evaluate(interpreter, source) {
+        Ok(value) => print_value(interpreter, "", value, line),
+        Err(LangError::RuntimeError { message, .. }) => println!("Error: {}", message),
+        Err(LangError::LimitExceeded { limit, .. }) => println!("Error: {}", limit.message()),
+        Err(_) => (),
+    }
+}
+
+fn print_locals(interpreter: &mut Interpreter) {
+    let mut locals = Vec::new();
+    let mut environment = Some(interpreter.environment.clone());
+    while let Some(env) = environment {
+        if env.enclosing.is_none() {
+            break;
+        }
+        let names = env.names.borrow();
+        let slots = env.slots.borrow();
+        locals.extend(names.iter().cloned().zip(slots.iter().cloned()));
+        environment = env.enclosing.clone();
+    }
+    if locals.is_empty() {
+        println!("No locals.");
+    }
+    for (name, value) in locals {
+        print_value(interpreter, &name, value, 0);
+    }
+}
+
+fn print_globals(interpreter: &mut Interpreter) {
+    let mut environment = interpreter.environment.clone();
+    while let Some(enclosing) = environment.enclosing.clone() {
+        environment = enclosing;
+    }
+    let mut globals: Vec<(String, Object)> = environment
+        .globals
+        .borrow()
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    globals.sort_by(|a, b| a.0.cmp(&b.0));
+    for (name, value) in globals {
+        print_value(interpreter, &name, value, 0);
+    }
+}
+
+/// Print a value the way `print` would, which may run an `__str` method
+fn print_value(interpreter: &mut Interpreter, name: &str, value: Object, line: u32) {
+    let token = Token::new(TokenType::Identifier, name.to_string(), None, line);
+    match interpreter.stringify(value, &token) {
+        Ok(string) if name.is_empty() => println!("{}", string),
+        Ok(string) => println!("{} = {}", name, string),
+        Err(LangError::RuntimeError { message, .. }) => println!("Error: {}", message),
+        Err(LangError::LimitExceeded { limit, .. }) => println!("Error: {}", limit.message()),
+        Err(_) => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::literal_type::LiteralType;
+
+    fn stops(debugger: &mut Debugger, line: u32, depth: usize) -> bool {
+        debugger.should_stop(line, depth)
+    }
+
+    #[test]
+    fn test_resume_modes() {
+        let mut debugger = Debugger::new("");
+        assert!(stops(&mut debugger, 1, 0));
+        // Another statement on the same line doesn't stop again
+        assert!(!stops(&mut debugger, 1, 0));
+
+        debugger.mode = resume_mode("next", 1).unwrap();
+        assert!(!stops(&mut debugger, 2, 2));
+        assert!(stops(&mut debugger, 3, 1));
+
+        debugger.mode = resume_mode("f", 2).unwrap();
+        assert!(!stops(&mut debugger, 4, 2));
+        assert!(stops(&mut debugger, 5, 1));
+
+        debugger.mode = resume_mode("finish", 2).unwrap();
+        debugger.tail_call(2);
+        assert!(stops(&mut debugger, 6, 2));
+
+        assert!(resume_mode("locals", 0).is_none());
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = Debugger::new("");
+        let mut interpreter = Interpreter::new();
+        debugger.run_command(&mut interpreter, "b", "4", 1);
+        debugger.run_command(&mut interpreter, "break", "7", 1);
+        debugger.run_command(&mut interpreter, "d", "7", 1);
+        debugger.mode = resume_mode("c", 0).unwrap();
+        assert!(!stops(&mut debugger, 3, 0));
+        assert!(stops(&mut debugger, 4, 0));
+        assert!(!stops(&mut debugger, 7, 0));
+    }
+
+    #[test]
+    fn test_reject_breakpoint_on_line_zero() {
+        let mut debugger = Debugger::new("print 1;");
+        let mut interpreter = Interpreter::new();
+        debugger.run_command(&mut interpreter, "b", "0", 1);
+        assert!(debugger.breakpoints.is_empty());
+        debugger.show_line(0, "");
+    }
+
+    #[test]
+    fn test_evaluate_in_the_current_frame() {
+        crate::environment::set_record_names(true);
+        let mut interpreter = Interpreter::new();
+        let frame = Environment::new(Some(interpreter.environment.clone()));
+        frame.define("x", Object::Value(LiteralType::Int(41)));
+        interpreter.environment = Environment::new(Some(frame));
+        interpreter
+            .environment
+            .define("y", Object::Value(LiteralType::Int(1)));
+        let value = evaluate(&mut interpreter, "x + y").ok().unwrap();
+        assert!(value.equals(&Object::Value(LiteralType::Int(42))));
+        assert!(evaluate(&mut interpreter, "x +").is_err());
+    }
+}
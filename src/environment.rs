@@ -1,80 +1,140 @@
-use std::{borrow::BorrowMut, cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    borrow::BorrowMut,
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
-use crate::{lang_error::LangError, object::Object, scanner::token::Token};
+use crate::{
+    gc::{self, Trace, Tracer},
+    lang_error::LangError,
+    object::Object,
+    scanner::token::Token,
+};
 
+thread_local! {
+    static RECORD_NAMES: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Keep the names of locals next to their slots so a debugger can show them
+pub fn set_record_names(record: bool) {
+    RECORD_NAMES.with(|cell| cell.set(record));
+}
+
+/// Locals live in slots the resolver assigns in declaration order, so they
+/// are found by `(depth, slot)` without hashing. Only the global environment,
+/// which has no enclosing one, keeps variables by name.
 #[derive(Debug, Default, Clone)]
 pub struct Environment {
     pub enclosing: Option<Rc<Environment>>,
-    pub values: RefCell<HashMap<String, Object>>,
+    pub slots: RefCell<Vec<Object>>,
+    /// Names for `slots`, only kept while recording names
+    pub names: RefCell<Vec<String>>,
+    pub globals: RefCell<HashMap<String, Object>>,
+    /// Globals declared with `const`. The resolver already rejects assigning
+    /// to a constant local, but globals can be assigned from code it resolved
+    /// before the constant existed.
+    constants: RefCell<HashSet<String>>,
 }
 
 impl Environment {
     pub fn new(enclosing: Option<Rc<Environment>>) -> Rc<Environment> {
-        Rc::new(Environment {
+        let environment = Rc::new(Environment {
             enclosing,
             ..Default::default()
-        })
+        });
+        gc::track_environment(&environment);
+        environment
     }
 
-    pub fn define(&self, name: String, value: Object) {
-        self.values.borrow_mut().insert(name, value);
+    /// Define a global by name, or a local in the next slot
+    pub fn define(&self, name: &str, value: Object) {
+        if self.enclosing.is_none() {
+            self.globals.borrow_mut().insert(name.to_string(), value);
+        } else {
+            self.slots.borrow_mut().push(value);
+            if RECORD_NAMES.with(Cell::get) {
+                self.names.borrow_mut().push(name.to_string());
+            }
+        }
     }
 
-    pub fn get(&self, name: &Token) -> Result<Object, LangError> {
-        if let Some(value) = self.values.borrow().get(&name.lexeme) {
-            return Ok(value.clone());
+    pub fn define_constant(&self, name: &str, value: Object) {
+        self.define(name, value);
+        if self.enclosing.is_none() {
+            self.constants.borrow_mut().insert(name.to_string());
         }
+    }
 
-        if let Some(environment) = &self.enclosing {
-            return environment.get(name);
+    /// A global constant can't be declared again, which would get around
+    /// `const`. Redeclaring a local is already a resolve error.
+    pub fn check_redeclare(&self, name: &Token) -> Result<(), LangError> {
+        if self.constants.borrow().contains(&name.lexeme) {
+            let message = format!("Can't redeclare constant '{}'.", name.lexeme);
+            return Err(LangError::RuntimeError {
+                message,
+                line: name.line,
+            });
         }
-
-        let message = format!("Undefined variable '{}'.", name.lexeme);
-        Err(LangError::RuntimeError {
-            message,
-            line: name.clone().line,
-        })
+        Ok(())
     }
 
-    pub fn get_at(&self, distance: usize, name: String, line: u32) -> Result<Object, LangError> {
-        if let Some(value) = self.ancestor(distance).values.borrow().get(&name) {
-            return Ok(value.clone());
-        } else {
-            let message = format!("Undefined variable '{}'.", name);
-            Err(LangError::RuntimeError { message, line })
+    pub fn get(&self, name: &Token) -> Result<Object, LangError> {
+        match self.globals.borrow().get(&name.lexeme) {
+            Some(value) => Ok(value.clone()),
+            None => Err(undefined_variable(&name.lexeme, name.line)),
         }
     }
 
-    pub fn assign(&self, name: Token, value: Object) -> Result<(), LangError> {
-        let mut values = self.values.borrow_mut();
-        if values.contains_key(&name.lexeme) {
-            values.insert(name.lexeme, value);
-            return Ok(());
+    pub fn get_at(
+        &self,
+        distance: usize,
+        slot: usize,
+        name: &str,
+        line: u32,
+    ) -> Result<Object, LangError> {
+        match self.ancestor(distance).slots.borrow().get(slot) {
+            Some(value) => Ok(value.clone()),
+            None => Err(undefined_variable(name, line)),
         }
+    }
 
-        if let Some(environment) = self.enclosing.clone() {
-            return environment.assign(name, value);
+    pub fn assign(&self, name: &Token, value: Object) -> Result<(), LangError> {
+        if self.constants.borrow().contains(&name.lexeme) {
+            let message = format!("Can't assign to constant '{}'.", name.lexeme);
+            return Err(LangError::RuntimeError {
+                message,
+                line: name.line,
+            });
+        }
+        match self.globals.borrow_mut().get_mut(&name.lexeme) {
+            Some(variable) => {
+                *variable = value;
+                Ok(())
+            }
+            None => Err(undefined_variable(&name.lexeme, name.line)),
         }
-
-        let message = format!("Undefined variable '{}'.", name.lexeme);
-        Err(LangError::RuntimeError {
-            message,
-            line: name.clone().line,
-        })
     }
 
-    pub fn assign_at(&self, distance: usize, name: Token, value: Object) -> Result<(), LangError> {
-        let mut values = self.ancestor(distance).values.borrow_mut();
-        if values.contains_key(&name.lexeme) {
-            values.insert(name.lexeme, value);
-            return Ok(());
+    pub fn assign_at(
+        &self,
+        distance: usize,
+        slot: usize,
+        name: &Token,
+        value: Object,
+    ) -> Result<(), LangError> {
+        match self.ancestor(distance).slots.borrow_mut().get_mut(slot) {
+            Some(variable) => {
+                *variable = value;
+                Ok(())
+            }
+            None => Err(undefined_variable(&name.lexeme, name.line)),
         }
+    }
 
-        let message = format!("Undefined variable '{}'.", name.lexeme);
-        Err(LangError::RuntimeError {
-            message,
-            line: name.clone().line,
-        })
+    /// The slot of a local, when names are being recorded
+    pub fn slot_of(&self, name: &str) -> Option<usize> {
+        self.names.borrow().iter().rposition(|local| local == name)
     }
 
     fn ancestor(&self, distance: usize) -> &Self {
@@ -85,3 +145,24 @@ impl Environment {
         env
     }
 }
+
+fn undefined_variable(name: &str, line: u32) -> LangError {
+    let message = format!("Undefined variable '{}'.", name);
+    LangError::RuntimeError { message, line }
+}
+
+impl Trace for Environment {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Some(enclosing) = &self.enclosing {
+            tracer.environment(enclosing);
+        }
+        match self.slots.try_borrow() {
+            Ok(slots) => slots.iter().for_each(|value| value.trace(tracer)),
+            Err(_) => tracer.pin(),
+        }
+        match self.globals.try_borrow() {
+            Ok(globals) => globals.values().for_each(|value| value.trace(tracer)),
+            Err(_) => tracer.pin(),
+        }
+    }
+}
@@ -1,23 +1,31 @@
+use std::rc::Rc;
+
 use crate::object::literal_type::LiteralType;
 use crate::scanner::token::Token;
+use crate::stmt::Function;
 
 pub trait Visitor<T> {
     fn visit_assign_expr(&mut self, expr: &Assign) -> T;
     fn visit_binary_expr(&mut self, expr: &Binary) -> T;
     fn visit_call_expr(&mut self, expr: &Call) -> T;
+    fn visit_conditional_expr(&mut self, expr: &Conditional) -> T;
     fn visit_get_expr(&mut self, expr: &Get) -> T;
     fn visit_grouping_expr(&mut self, expr: &Grouping) -> T;
+    fn visit_index_expr(&mut self, expr: &Index) -> T;
+    fn visit_lambda_expr(&mut self, expr: &Lambda) -> T;
     fn visit_literal_expr(&mut self, expr: &Literal) -> T;
     fn visit_logical_expr(&mut self, expr: &Logical) -> T;
+    fn visit_range_expr(&mut self, expr: &Range) -> T;
     fn visit_set_expr(&mut self, expr: &Set) -> T;
     fn visit_super_expr(&mut self, expr: &Super) -> T;
     fn visit_this_expr(&mut self, expr: &This) -> T;
+    fn visit_tuple_expr(&mut self, expr: &Tuple) -> T;
     fn visit_unary_expr(&mut self, expr: &Unary) -> T;
     fn visit_variable_expr(&mut self, expr: &Variable) -> T;
 }
 
 pub trait Accept<T> {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T;
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T;
 }
 
 #[derive(Clone, Debug)]
@@ -25,30 +33,40 @@ pub enum Expr {
     Assign(Box<Assign>),
     Binary(Box<Binary>),
     Call(Box<Call>),
+    Conditional(Conditional),
     Get(Box<Get>),
     Grouping(Box<Grouping>),
+    Index(Box<Index>),
+    Lambda(Lambda),
     Literal(Literal),
     Logical(Box<Logical>),
+    Range(Range),
     Set(Box<Set>),
     Super(Super),
     This(This),
+    Tuple(Tuple),
     Unary(Box<Unary>),
     Variable(Variable),
 }
 
 impl<T> Accept<T> for Expr {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         match self {
             Expr::Assign(e) => e.accept(visitor),
             Expr::Binary(e) => e.accept(visitor),
             Expr::Call(e) => e.accept(visitor),
+            Expr::Conditional(e) => e.accept(visitor),
             Expr::Get(e) => e.accept(visitor),
             Expr::Grouping(e) => e.accept(visitor),
+            Expr::Index(e) => e.accept(visitor),
+            Expr::Lambda(e) => e.accept(visitor),
             Expr::Literal(e) => e.accept(visitor),
             Expr::Logical(e) => e.accept(visitor),
+            Expr::Range(e) => e.accept(visitor),
             Expr::Set(e) => e.accept(visitor),
             Expr::Super(e) => e.accept(visitor),
             Expr::This(e) => e.accept(visitor),
+            Expr::Tuple(e) => e.accept(visitor),
             Expr::Unary(e) => e.accept(visitor),
             Expr::Variable(e) => e.accept(visitor),
         }
@@ -57,18 +75,33 @@ impl<T> Accept<T> for Expr {
 
 #[derive(Clone, Debug)]
 pub struct Assign {
+    pub id: u64,
     pub name: Token,
+    pub operator: Option<Token>,
     pub value: Box<Expr>,
+    pub postfix: bool,
 }
 
 impl Assign {
-    pub fn new(name: Token, value: Box<Expr>) -> Box<Assign> {
-        Box::new(Assign { name, value })
+    pub fn new(
+        id: u64,
+        name: Token,
+        operator: Option<Token>,
+        value: Box<Expr>,
+        postfix: bool,
+    ) -> Box<Assign> {
+        Box::new(Assign {
+            id,
+            name,
+            operator,
+            value,
+            postfix,
+        })
     }
 }
 
 impl<T> Accept<T> for Assign {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_assign_expr(self)
     }
 }
@@ -91,7 +124,7 @@ impl Binary {
 }
 
 impl<T> Accept<T> for Binary {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_binary_expr(self)
     }
 }
@@ -101,38 +134,77 @@ pub struct Call {
     pub callee: Box<Expr>,
     pub paren: Token,
     pub arguments: Vec<Expr>,
+    pub names: Vec<Token>,
 }
 
 impl Call {
-    pub fn new(callee: Box<Expr>, paren: Token, arguments: Vec<Expr>) -> Box<Call> {
+    pub fn new(
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+        names: Vec<Token>,
+    ) -> Box<Call> {
         Box::new(Call {
             callee,
             paren,
             arguments,
+            names,
         })
     }
 }
 
 impl<T> Accept<T> for Call {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_call_expr(self)
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Conditional {
+    pub condition: Box<Expr>,
+    pub then_branch: Box<Expr>,
+    pub else_branch: Box<Expr>,
+}
+
+impl Conditional {
+    pub fn new(
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+    ) -> Conditional {
+        Conditional {
+            condition,
+            then_branch,
+            else_branch,
+        }
+    }
+}
+
+impl<T> Accept<T> for Conditional {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_conditional_expr(self)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Get {
     pub object: Box<Expr>,
     pub name: Token,
+    pub optional: bool,
 }
 
 impl Get {
-    pub fn new(object: Box<Expr>, name: Token) -> Box<Get> {
-        Box::new(Get { object, name })
+    pub fn new(object: Box<Expr>, name: Token, optional: bool) -> Box<Get> {
+        Box::new(Get {
+            object,
+            name,
+            optional,
+        })
     }
 }
 
 impl<T> Accept<T> for Get {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_get_expr(self)
     }
 }
@@ -149,11 +221,51 @@ impl Grouping {
 }
 
 impl<T> Accept<T> for Grouping {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_grouping_expr(self)
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Index {
+    pub object: Box<Expr>,
+    pub bracket: Token,
+    pub index: Box<Expr>,
+}
+
+impl Index {
+    pub fn new(object: Box<Expr>, bracket: Token, index: Box<Expr>) -> Box<Index> {
+        Box::new(Index {
+            object,
+            bracket,
+            index,
+        })
+    }
+}
+
+impl<T> Accept<T> for Index {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_index_expr(self)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Lambda {
+    pub function: Rc<Function>,
+}
+
+impl Lambda {
+    pub fn new(function: Rc<Function>) -> Lambda {
+        Lambda { function }
+    }
+}
+
+impl<T> Accept<T> for Lambda {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_lambda_expr(self)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Literal {
     pub value: LiteralType,
@@ -166,7 +278,7 @@ impl Literal {
 }
 
 impl<T> Accept<T> for Literal {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_literal_expr(self)
     }
 }
@@ -189,69 +301,125 @@ impl Logical {
 }
 
 impl<T> Accept<T> for Logical {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_logical_expr(self)
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Range {
+    pub start: Box<Expr>,
+    pub operator: Token,
+    pub end: Box<Expr>,
+}
+
+impl Range {
+    pub fn new(start: Box<Expr>, operator: Token, end: Box<Expr>) -> Range {
+        Range {
+            start,
+            operator,
+            end,
+        }
+    }
+}
+
+impl<T> Accept<T> for Range {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_range_expr(self)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Set {
     pub object: Box<Expr>,
     pub name: Token,
+    pub operator: Option<Token>,
     pub value: Box<Expr>,
+    pub postfix: bool,
 }
 
 impl Set {
-    pub fn new(object: Box<Expr>, name: Token, value: Box<Expr>) -> Box<Set> {
+    pub fn new(
+        object: Box<Expr>,
+        name: Token,
+        operator: Option<Token>,
+        value: Box<Expr>,
+        postfix: bool,
+    ) -> Box<Set> {
         Box::new(Set {
             object,
             name,
+            operator,
             value,
+            postfix,
         })
     }
 }
 
 impl<T> Accept<T> for Set {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_set_expr(self)
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Super {
+    pub id: u64,
     pub keyword: Token,
     pub method: Token,
 }
 
 impl Super {
-    pub fn new(keyword: Token, method: Token) -> Super {
-        Super { keyword, method }
+    pub fn new(id: u64, keyword: Token, method: Token) -> Super {
+        Super {
+            id,
+            keyword,
+            method,
+        }
     }
 }
 
 impl<T> Accept<T> for Super {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_super_expr(self)
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct This {
+    pub id: u64,
     pub keyword: Token,
 }
 
 impl This {
-    pub fn new(keyword: Token) -> This {
-        This { keyword }
+    pub fn new(id: u64, keyword: Token) -> This {
+        This { id, keyword }
     }
 }
 
 impl<T> Accept<T> for This {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_this_expr(self)
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Tuple {
+    pub elements: Vec<Expr>,
+}
+
+impl Tuple {
+    pub fn new(elements: Vec<Expr>) -> Tuple {
+        Tuple { elements }
+    }
+}
+
+impl<T> Accept<T> for Tuple {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_tuple_expr(self)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Unary {
     pub operator: Token,
@@ -265,24 +433,25 @@ impl Unary {
 }
 
 impl<T> Accept<T> for Unary {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_unary_expr(self)
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Variable {
+    pub id: u64,
     pub name: Token,
 }
 
 impl Variable {
-    pub fn new(name: Token) -> Variable {
-        Variable { name }
+    pub fn new(id: u64, name: Token) -> Variable {
+        Variable { id, name }
     }
 }
 
 impl<T> Accept<T> for Variable {
-    fn accept(&mut self, visitor: &mut impl Visitor<T>) -> T {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_variable_expr(self)
     }
 }
@@ -0,0 +1,494 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    environment::Environment,
+    object::{
+        callable::lox_class::LoxClass, lox_generator::LoxGenerator, lox_instance::LoxInstance,
+        lox_list::LoxList, lox_trait::LoxTrait, LoxCallable,
+    },
+};
+
+const INITIAL_THRESHOLD: usize = 1024;
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap::default());
+}
+
+/// Counters describing what the collector has done so far
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcStats {
+    pub allocated: usize,
+    pub collections: usize,
+    pub freed: usize,
+    pub live: usize,
+}
+
+/// Something that holds strong references to heap objects
+pub trait Trace {
+    fn trace(&self, tracer: &mut Tracer);
+}
+
+/// Collects the outgoing edges of the heap object being traced
+#[derive(Default)]
+pub struct Tracer {
+    edges: Vec<usize>,
+    pinned: bool,
+}
+
+impl Tracer {
+    pub fn environment(&mut self, environment: &Rc<Environment>) {
+        self.edges.push(address(environment));
+    }
+
+    pub fn instance(&mut self, instance: &Rc<LoxInstance>) {
+        self.edges.push(address(instance));
+    }
+
+    pub fn class(&mut self, class: &Rc<LoxClass>) {
+        self.edges.push(address(class));
+    }
+
+    pub fn function(&mut self, function: &Rc<dyn LoxCallable>) {
+        self.edges.push(address(function));
+    }
+
+    pub fn lox_trait(&mut self, lox_trait: &Rc<LoxTrait>) {
+        self.edges.push(address(lox_trait));
+    }
+
+    pub fn list(&mut self, list: &Rc<LoxList>) {
+        self.edges.push(address(list));
+    }
+
+    pub fn generator(&mut self, generator: &Rc<LoxGenerator>) {
+        self.edges.push(address(generator));
+    }
+
+    /// Keep the traced object alive because its contents can't be inspected right now
+    pub fn pin(&mut self) {
+        self.pinned = true;
+    }
+}
+
+enum HeapObject {
+    Environment(Weak<Environment>),
+    Instance(Weak<LoxInstance>),
+    Class(Weak<LoxClass>),
+    Function(Weak<dyn LoxCallable>),
+    Trait(Weak<LoxTrait>),
+    List(Weak<LoxList>),
+    Generator(Weak<LoxGenerator>),
+}
+
+enum Handle {
+    Environment(Rc<Environment>),
+    Instance(Rc<LoxInstance>),
+    Class(Rc<LoxClass>),
+    Function(Rc<dyn LoxCallable>),
+    Trait(Rc<LoxTrait>),
+    List(Rc<LoxList>),
+    Generator(Rc<LoxGenerator>),
+}
+
+impl HeapObject {
+    fn upgrade(&self) -> Option<Handle> {
+        match self {
+            HeapObject::Environment(env) => env.upgrade().map(Handle::Environment),
+            HeapObject::Instance(instance) => instance.upgrade().map(Handle::Instance),
+            HeapObject::Class(class) => class.upgrade().map(Handle::Class),
+            HeapObject::Function(function) => function.upgrade().map(Handle::Function),
+            HeapObject::Trait(lox_trait) => lox_trait.upgrade().map(Handle::Trait),
+            HeapObject::List(list) => list.upgrade().map(Handle::List),
+            HeapObject::Generator(generator) => generator.upgrade().map(Handle::Generator),
+        }
+    }
+}
+
+impl Handle {
+    fn downgrade(&self) -> HeapObject {
+        match self {
+            Handle::Environment(env) => HeapObject::Environment(Rc::downgrade(env)),
+            Handle::Instance(instance) => HeapObject::Instance(Rc::downgrade(instance)),
+            Handle::Class(class) => HeapObject::Class(Rc::downgrade(class)),
+            Handle::Function(function) => HeapObject::Function(Rc::downgrade(function)),
+            Handle::Trait(lox_trait) => HeapObject::Trait(Rc::downgrade(lox_trait)),
+            Handle::List(list) => HeapObject::List(Rc::downgrade(list)),
+            Handle::Generator(generator) => HeapObject::Generator(Rc::downgrade(generator)),
+        }
+    }
+
+    fn address(&self) -> usize {
+        match self {
+            Handle::Environment(env) => address(env),
+            Handle::Instance(instance) => address(instance),
+            Handle::Class(class) => address(class),
+            Handle::Function(function) => address(function),
+            Handle::Trait(lox_trait) => address(lox_trait),
+            Handle::List(list) => address(list),
+            Handle::Generator(generator) => address(generator),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Handle::Environment(env) => Rc::strong_count(env),
+            Handle::Instance(instance) => Rc::strong_count(instance),
+            Handle::Class(class) => Rc::strong_count(class),
+            Handle::Function(function) => Rc::strong_count(function),
+            Handle::Trait(lox_trait) => Rc::strong_count(lox_trait),
+            Handle::List(list) => Rc::strong_count(list),
+            Handle::Generator(generator) => Rc::strong_count(generator),
+        }
+    }
+
+    fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            Handle::Environment(env) => env.trace(tracer),
+            Handle::Instance(instance) => instance.trace(tracer),
+            // `Rc<LoxClass>` traces as an edge to the class, so name the impl
+            Handle::Class(class) => LoxClass::trace(class, tracer),
+            Handle::Function(function) => function.trace(tracer),
+            Handle::Trait(lox_trait) => lox_trait.trace(tracer),
+            Handle::List(list) => list.trace(tracer),
+            Handle::Generator(generator) => generator.trace(tracer),
+        }
+    }
+
+    /// Drop everything the object refers to, which breaks any cycle running through it
+    fn clear(&self) {
+        match self {
+            Handle::Environment(env) => {
+                if let Ok(mut slots) = env.slots.try_borrow_mut() {
+                    slots.clear();
+                }
+                if let Ok(mut globals) = env.globals.try_borrow_mut() {
+                    globals.clear();
+                }
+            }
+            Handle::Instance(instance) => instance.clear(),
+            Handle::Class(class) => class.clear(),
+            Handle::List(list) => list.clear(),
+            Handle::Generator(generator) => generator.clear(),
+            // Closures are never reassigned, so a cycle through a function or
+            // a trait always passes through something else that can be cleared
+            Handle::Function(_) | Handle::Trait(_) => (),
+        }
+    }
+}
+
+struct Heap {
+    objects: Vec<HeapObject>,
+    next_gc: usize,
+    stress: bool,
+    collecting: bool,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Heap {
+            objects: Vec::new(),
+            next_gc: INITIAL_THRESHOLD,
+            stress: false,
+            collecting: false,
+            stats: GcStats::default(),
+        }
+    }
+}
+
+fn address<T: ?Sized>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+pub fn track_environment(environment: &Rc<Environment>) {
+    track(HeapObject::Environment(Rc::downgrade(environment)));
+}
+
+pub fn track_instance(instance: &Rc<LoxInstance>) {
+    track(HeapObject::Instance(Rc::downgrade(instance)));
+}
+
+pub fn track_class(class: &Rc<LoxClass>) {
+    track(HeapObject::Class(Rc::downgrade(class)));
+}
+
+pub fn track_function(function: &Rc<dyn LoxCallable>) {
+    track(HeapObject::Function(Rc::downgrade(function)));
+}
+
+pub fn track_trait(lox_trait: &Rc<LoxTrait>) {
+    track(HeapObject::Trait(Rc::downgrade(lox_trait)));
+}
+
+pub fn track_list(list: &Rc<LoxList>) {
+    track(HeapObject::List(Rc::downgrade(list)));
+}
+
+pub fn track_generator(generator: &Rc<LoxGenerator>) {
+    track(HeapObject::Generator(Rc::downgrade(generator)));
+}
+
+fn track(object: HeapObject) {
+    let should_collect = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.push(object);
+        heap.stats.allocated += 1;
+        !heap.collecting && (heap.stress || heap.objects.len() >= heap.next_gc)
+    });
+    if should_collect {
+        collect();
+    }
+}
+
+/// Objects tracked since the last collection, some of which may already be
+/// gone. Right after `collect` it is exactly the number of live objects.
+pub fn tracked() -> usize {
+    HEAP.with(|heap| heap.borrow().objects.len())
+}
+
+/// Forget the tracked objects that reference counting has already freed and
+/// return how many are left. Much cheaper than `collect`, which also has to
+/// trace the survivors to find cycles.
+pub fn prune() -> usize {
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.retain(|object| object.upgrade().is_some());
+        heap.objects.len()
+    })
+}
+
+/// Collect after every allocation, which shakes out objects that are freed too early
+pub fn set_stress(stress: bool) {
+    HEAP.with(|heap| heap.borrow_mut().stress = stress);
+}
+
+pub fn stats() -> GcStats {
+    HEAP.with(|heap| {
+        let heap = heap.borrow();
+        let live = heap
+            .objects
+            .iter()
+            .filter(|object| object.upgrade().is_some())
+            .count();
+        GcStats { live, ..heap.stats }
+    })
+}
+
+/// Mark and sweep over every tracked object.
+///
+/// Roots are the objects referenced from outside the heap: the interpreter's
+/// globals and environment stack, and any value the interpreter is holding on
+/// the Rust stack. They are found by comparing each object's strong count with
+/// the number of references coming from other heap objects. Whatever can't be
+/// reached from a root is only kept alive by a cycle and gets cleared.
+pub fn collect() {
+    let handles: Vec<Handle> = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.collecting = true;
+        let handles: Vec<Handle> = heap
+            .objects
+            .iter()
+            .filter_map(HeapObject::upgrade)
+            .collect();
+        heap.objects = handles.iter().map(Handle::downgrade).collect();
+        handles
+    });
+
+    let index: HashMap<usize, usize> = handles
+        .iter()
+        .enumerate()
+        .map(|(i, handle)| (handle.address(), i))
+        .collect();
+    let mut internal_refs = vec![0; handles.len()];
+    let mut edges = vec![Vec::new(); handles.len()];
+    let mut pinned = vec![false; handles.len()];
+    for (i, handle) in handles.iter().enumerate() {
+        let mut tracer = Tracer::default();
+        handle.trace(&mut tracer);
+        pinned[i] = tracer.pinned;
+        for edge in tracer.edges {
+            if let Some(&j) = index.get(&edge) {
+                internal_refs[j] += 1;
+                edges[i].push(j);
+            }
+        }
+    }
+
+    // `handles` itself holds one strong reference to every object
+    let mut marked = vec![false; handles.len()];
+    let mut worklist: Vec<usize> = (0..handles.len())
+        .filter(|&i| pinned[i] || handles[i].strong_count() - 1 > internal_refs[i])
+        .collect();
+    while let Some(i) = worklist.pop() {
+        if marked[i] {
+            continue;
+        }
+        marked[i] = true;
+        worklist.extend(edges[i].iter().filter(|&&j| !marked[j]));
+    }
+
+    let mut freed = 0;
+    for (handle, _) in handles.iter().zip(marked).filter(|(_, marked)| !marked) {
+        handle.clear();
+        freed += 1;
+    }
+    drop(handles);
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.retain(|object| object.upgrade().is_some());
+        heap.next_gc = INITIAL_THRESHOLD.max(heap.objects.len() * 2);
+        heap.stats.collections += 1;
+        heap.stats.freed += freed;
+        heap.collecting = false;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{
+        callable::{lox_class::LoxClass, lox_function::LoxFunction},
+        Object,
+    };
+    use crate::scanner::token::{Token, TokenType};
+    use crate::stmt::Function;
+
+    fn function(closure: Rc<Environment>) -> Object {
+        let name = Token::new(TokenType::Identifier, "f".to_string(), None, 1);
+        let declaration = Rc::new(Function::new(
+            name,
+            Vec::new(),
+            Vec::new(),
+            None,
+            Vec::new(),
+            false,
+        ));
+        Object::function(LoxFunction::new(declaration, closure, false))
+    }
+
+    #[test]
+    fn test_collect_closure_cycle() {
+        let environment = Environment::new(None);
+        environment.define("f", function(environment.clone()));
+        let weak = Rc::downgrade(&environment);
+        drop(environment);
+        assert!(weak.upgrade().is_some());
+        collect();
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_keep_reachable_objects() {
+        let globals = Environment::new(None);
+        let local = Environment::new(Some(globals.clone()));
+        local.define("f", function(local.clone()));
+        globals.define("f", function(local.clone()));
+        let weak = Rc::downgrade(&local);
+        drop(local);
+        collect();
+        assert!(weak.upgrade().is_some());
+        drop(globals);
+        collect();
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_keep_closures_of_live_classes() {
+        let closure = Environment::new(None);
+        let name = Token::new(TokenType::Identifier, "m".to_string(), None, 1);
+        let declaration = Rc::new(Function::new(
+            name,
+            Vec::new(),
+            Vec::new(),
+            None,
+            Vec::new(),
+            false,
+        ));
+        let mut methods = HashMap::new();
+        methods.insert(
+            "m".to_string(),
+            LoxFunction::new(declaration, closure.clone(), false),
+        );
+        let class = LoxClass::new(
+            "Node".to_string(),
+            None,
+            methods,
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let weak = Rc::downgrade(&closure);
+        drop(closure);
+        collect();
+        assert!(weak.upgrade().is_some());
+        drop(class);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_collect_self_referencing_instance() {
+        let class = LoxClass::new(
+            "Node".to_string(),
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let instance = LoxInstance::new(class);
+        let name = Token::new(TokenType::Identifier, "me".to_string(), None, 1);
+        instance.set(&name, Object::Instance(instance.clone()));
+        let weak = Rc::downgrade(&instance);
+        drop(instance);
+        collect();
+        assert!(weak.upgrade().is_none());
+        assert!(stats().freed >= 1);
+    }
+
+    #[test]
+    fn test_collect_list_containing_itself() {
+        let list = LoxList::new(Vec::new());
+        list.elements.borrow_mut().push(Object::List(list.clone()));
+        let weak = Rc::downgrade(&list);
+        drop(list);
+        collect();
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_collect_class_closure_cycle() {
+        let closure = Environment::new(None);
+        let name = Token::new(TokenType::Identifier, "m".to_string(), None, 1);
+        let declaration = Rc::new(Function::new(
+            name,
+            Vec::new(),
+            Vec::new(),
+            None,
+            Vec::new(),
+            false,
+        ));
+        let mut methods = HashMap::new();
+        methods.insert(
+            "m".to_string(),
+            LoxFunction::new(declaration, closure.clone(), false),
+        );
+        let class = LoxClass::new(
+            "Node".to_string(),
+            None,
+            methods,
+            HashMap::new(),
+            HashMap::new(),
+        );
+        closure.define("Node", Object::Class(class.clone()));
+        let weak_class = Rc::downgrade(&class);
+        let weak_closure = Rc::downgrade(&closure);
+        drop(class);
+        drop(closure);
+        collect();
+        assert!(weak_class.upgrade().is_none());
+        assert!(weak_closure.upgrade().is_none());
+    }
+}
@@ -1,113 +1,946 @@
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
+use crate::cancel::CancelHandle;
+use crate::debugger::Debugger;
 use crate::environment::Environment;
 use crate::expr::{self, Accept as AcceptExpr, Binary, Expr, Grouping, Literal, Unary};
-use crate::lang_error::LangError;
-use crate::object::callable::global_function::Clock;
+use crate::gc::{self, Trace, Tracer};
+use crate::lang_error::{LangError, Limit};
+use crate::line;
+use crate::object::callable::global_function::{Clock, Freeze};
 use crate::object::callable::lox_class::LoxClass;
 use crate::object::callable::lox_function::LoxFunction;
 use crate::object::literal_type::{self, LiteralType};
-use crate::object::{LoxCallable, Object};
+use crate::object::lox_generator::{GeneratorMethod, LoxGenerator};
+use crate::object::lox_instance::LoxInstance;
+use crate::object::lox_list::LoxList;
+use crate::object::lox_trait::LoxTrait;
+use crate::object::{Arguments, LoxCallable, Object};
+use crate::pattern::{Pattern, VarPattern};
+use crate::profiler::Profiler;
 use crate::scanner::token::*;
 use crate::stmt::{self, Accept as AcceptStmt, Stmt};
 
+/// Deep enough for real programs. Needs far more native stack than a thread
+/// gets by default, see `Interpreter::new`.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+/// The native stack one Lox call can take in a debug build, with room to spare
+/// for calls made from deep inside nested statements and expressions
+pub const STACK_PER_CALL: usize = 32 * 1024;
+
+/// Reading the clock on every statement would be noticeable, so the
+/// timeout is checked once per this many statements
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
+/// Bounds for running scripts that aren't trusted. A script that crosses
+/// one stops with `LangError::LimitExceeded`.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Statements executed by one call to `interpret`
+    pub max_steps: Option<u64>,
+    /// Wall-clock time for one call to `interpret`
+    pub timeout: Option<Duration>,
+    pub max_call_depth: usize,
+    /// Live environments, instances, classes, functions, traits, lists and generators
+    pub max_objects: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            timeout: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_objects: None,
+        }
+    }
+}
+
+/// How a statement finished, which lets returns and loop control skip the
+/// statements after it without being mistaken for errors
+pub enum ExecResult {
+    Normal,
+    Return(Object),
+    /// `return f(x)`, made by the caller once the returning function has unwound
+    TailCall(Box<TailCall>),
+    Break,
+    Continue,
+    /// `yield value`, which suspends the generator running the statement
+    Yield(Object),
+}
+
+pub struct TailCall {
+    pub callee: Object,
+    pub arguments: Arguments,
+    pub paren: Token,
+}
+
+/// How far a `for-in` loop has got through what it iterates over
+#[derive(Debug)]
+pub enum Iteration {
+    /// Read by index, so elements added during the loop are visited too
+    List(Rc<LoxList>, usize),
+    Chars(std::vec::IntoIter<char>),
+    /// The next number and the end, which is left out
+    Range(i64, i64),
+    /// An object with `hasNext()` and `next()` methods
+    Iterator(Rc<LoxInstance>),
+    Generator(Rc<LoxGenerator>),
+}
+
+impl Trace for Iteration {
+    fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            Iteration::List(list, _) => tracer.list(list),
+            Iteration::Iterator(iterator) => tracer.instance(iterator),
+            Iteration::Generator(generator) => tracer.generator(generator),
+            Iteration::Chars(_) | Iteration::Range(..) => (),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Interpreter {
     pub environment: Rc<Environment>,
     globals: Rc<Environment>,
-    locals: HashMap<u64, usize>,
+    /// The `(depth, slot)` of each local variable, by expression id
+    locals: HashMap<u64, (usize, usize)>,
+    call_depth: usize,
+    pub limits: Limits,
+    steps: u64,
+    deadline: Option<Instant>,
+    cancel: CancelHandle,
+    pub profiler: Option<Profiler>,
+    pub debugger: Option<Debugger>,
 }
 
 impl Interpreter {
+    /// Lox calls nest on the native stack, so the thread running the
+    /// interpreter needs `limits.max_call_depth * STACK_PER_CALL` bytes of
+    /// it, about 320 MiB with the default limits. On an ordinary thread
+    /// either spawn one with `thread::Builder::stack_size` or lower
+    /// `max_call_depth`, or a deep recursion aborts the process instead of
+    /// stopping with "Stack overflow.".
     pub fn new() -> Interpreter {
         let globals = Environment::new(None);
-        let clock_function = Object::Function(Box::new(Clock::new()));
-        globals.define("clock".to_string(), clock_function);
+        let clock_function = Object::function(Clock::new());
+        globals.define("clock", clock_function);
+        globals.define("freeze", Object::function(Freeze::new()));
         Interpreter {
             environment: globals.clone(),
             globals: globals.clone(),
             locals: HashMap::new(),
+            call_depth: 0,
+            limits: Limits::default(),
+            steps: 0,
+            deadline: None,
+            cancel: CancelHandle::default(),
+            profiler: None,
+            debugger: None,
         }
     }
 
     pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), LangError> {
+        self.steps = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
         for statement in statements {
             self.execute(&statement)?;
         }
         Ok(())
     }
 
-    fn execute(&mut self, statement: &Stmt) -> Result<(), LangError> {
-        statement.accept(self)
+    pub fn execute(&mut self, statement: &Stmt) -> Result<ExecResult, LangError> {
+        self.before_statement(statement)?;
+        let profiled_line = self.profiler.as_mut().and_then(|profiler| {
+            let line = line::stmt_line(statement)?;
+            profiler.enter_line(line);
+            Some(line)
+        });
+        let result = statement.accept(self);
+        if let (Some(profiler), Some(_)) = (&mut self.profiler, profiled_line) {
+            profiler.exit_line();
+        }
+        result.map_err(|error| on_statement_line(error, statement))
+    }
+
+    /// Stop in the debugger and check the limits before running `statement`.
+    /// Generators call this for the statements they run a piece at a time.
+    pub fn before_statement(&mut self, statement: &Stmt) -> Result<(), LangError> {
+        if self.debugger.is_some() {
+            self.pause_in_debugger(statement);
+        }
+        self.check_limits()
+            .map_err(|error| on_statement_line(error, statement))
+    }
+
+    /// Count a hit on the line of a statement a generator runs a piece at a
+    /// time. Its time isn't counted, since other code runs between the pieces.
+    pub fn profile_hit(&mut self, statement: &Stmt) {
+        if let (Some(profiler), Some(line)) = (&mut self.profiler, line::stmt_line(statement)) {
+            profiler.enter_line(line);
+            profiler.exit_line();
+        }
+    }
+
+    /// Count a step and stop the script if it has gone past any of its limits
+    fn check_limits(&mut self) -> Result<(), LangError> {
+        self.steps += 1;
+        let limit = if self.limits.max_steps.is_some_and(|max| self.steps > max) {
+            Limit::Steps
+        } else if self.steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL)
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            Limit::Timeout
+        } else if self.limits.max_objects.is_some_and(too_many_objects) {
+            Limit::Objects
+        } else {
+            return Ok(());
+        };
+        Err(LangError::LimitExceeded { limit, line: 0 })
+    }
+
+    /// Hand control to the debugger, which is taken out meanwhile so that
+    /// code it runs, such as a printed expression, doesn't stop again
+    fn pause_in_debugger(&mut self, statement: &Stmt) {
+        if let Some(line) = line::stmt_line(statement) {
+            let mut debugger = self.debugger.take().unwrap();
+            debugger.before_statement(self, line);
+            self.debugger = Some(debugger);
+        }
+    }
+
+    /// A handle that stops whatever this interpreter is running, from any thread
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    pub fn check_cancelled(&self, line: u32) -> Result<(), LangError> {
+        if self.cancel.take() {
+            return Err(LangError::Interrupted { line });
+        }
+        Ok(())
+    }
+
+    pub fn call_depth(&self) -> usize {
+        self.call_depth
     }
 
     pub fn execute_block(
         &mut self,
-        statements: &Vec<Stmt>,
+        statements: &[Stmt],
         environment: Rc<Environment>,
-    ) -> Result<(), LangError> {
+    ) -> Result<ExecResult, LangError> {
         let previous_environment = self.environment.clone();
         self.environment = environment.clone();
-        let result = || -> Result<(), LangError> {
+        let result = || -> Result<ExecResult, LangError> {
             for statement in statements {
-                self.execute(statement)?;
+                match self.execute(statement)? {
+                    ExecResult::Normal => (),
+                    result => return Ok(result),
+                }
             }
-            Ok(())
+            Ok(ExecResult::Normal)
         }();
         self.environment = previous_environment;
         result
     }
 
-    fn evaluate(&mut self, expr: &Box<Expr>) -> Result<Object, LangError> {
-        expr.clone().accept(self)
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Object, LangError> {
+        expr.accept(self)
     }
 
-    pub fn resolve(&mut self, token_id: u64, depth: usize) {
-        self.locals.insert(token_id, depth);
+    /// Evaluate an expression in another environment, such as a parameter's default value
+    pub fn evaluate_in(
+        &mut self,
+        expr: &Expr,
+        environment: Rc<Environment>,
+    ) -> Result<Object, LangError> {
+        let previous_environment = std::mem::replace(&mut self.environment, environment);
+        let result = self.evaluate(expr);
+        self.environment = previous_environment;
+        result
     }
 
-    fn look_up_variable(&self, name: Token) -> Result<Object, LangError> {
-        if let Some(distance) = self.locals.get(&name.id) {
-            return self.environment.get_at(*distance, name.lexeme, name.line);
+    pub fn resolve(&mut self, expr_id: u64, depth: usize, slot: usize) {
+        self.locals.insert(expr_id, (depth, slot));
+    }
+
+    fn look_up_variable(&self, expr_id: u64, name: &Token) -> Result<Object, LangError> {
+        if let Some(&(distance, slot)) = self.locals.get(&expr_id) {
+            return self
+                .environment
+                .get_at(distance, slot, &name.lexeme, name.line);
         }
-        self.globals.get(&name)
+        self.globals.get(name)
     }
 
     fn call_callable(
         &mut self,
-        callable: Box<dyn LoxCallable>,
-        arguments: Vec<LiteralType>,
-        token: Token,
+        callable: &dyn LoxCallable,
+        arguments: Arguments,
+        token: &Token,
     ) -> Result<Object, LangError> {
-        if callable.arity() != arguments.len() {
-            let message = format!(
-                "Expected {} arguments but got {}.",
-                callable.arity(),
-                arguments.len()
-            );
+        if self.call_depth >= self.limits.max_call_depth {
+            return Err(LangError::LimitExceeded {
+                limit: Limit::CallDepth,
+                line: token.line,
+            });
+        }
+        check_arity(callable, &arguments, token)?;
+        self.check_cancelled(token.line)?;
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_function(callable.name());
+        }
+        self.call_depth += 1;
+        let result = callable.call(self, arguments);
+        self.call_depth -= 1;
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_function();
+        }
+        result
+    }
+
+    /// Make the running function's frame the callee's for an in-place tail
+    /// call, which `call_callable` doesn't see
+    pub fn replace_frame(&mut self, name: String) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit_function();
+            profiler.enter_function(name);
+        }
+        if let Some(debugger) = &mut self.debugger {
+            debugger.tail_call(self.call_depth);
+        }
+    }
+
+    pub fn call_object(
+        &mut self,
+        callee: Object,
+        arguments: Arguments,
+        paren: &Token,
+    ) -> Result<Object, LangError> {
+        match callee {
+            Object::Class(class) => self.call_callable(&class, arguments, paren),
+            Object::Function(func) => self.call_callable(&*func, arguments, paren),
+            Object::Instance(instance) => {
+                let arguments = arguments.into_positional()?;
+                match self.call_special_method(&instance, "__call", arguments, paren)? {
+                    Some(ret) => Ok(ret),
+                    None => Err(LangError::RuntimeError {
+                        message: "Can only call functions and classes.".to_string(),
+                        line: paren.line,
+                    }),
+                }
+            }
+            _ => Err(LangError::RuntimeError {
+                message: "Can only call functions and classes.".to_string(),
+                line: paren.line,
+            }),
+        }
+    }
+
+    /// The Lox function a tail call goes to, if any, so that the returning
+    /// function can run it in place instead of nesting another call
+    pub fn tail_call_target(&self, tail_call: &TailCall) -> Result<Option<LoxFunction>, LangError> {
+        let function = match &tail_call.callee {
+            Object::Function(function) => function.as_lox_function().cloned(),
+            Object::Instance(instance) if tail_call.arguments.named.is_empty() => instance
+                .class
+                .find_method("__call")
+                .map(|method| method.bind(Object::Instance(instance.clone()))),
+            _ => None,
+        };
+        if let Some(function) = &function {
+            check_arity(function, &tail_call.arguments, &tail_call.paren)?;
+            self.check_cancelled(tail_call.paren.line)?;
+        }
+        Ok(function)
+    }
+
+    /// The value of a property access, index or call, or `None` when a `?.`
+    /// in its chain found nil. That makes the rest of the chain nil without
+    /// evaluating it, so `a?.b.c()` is nil when `a` is.
+    fn evaluate_link(&mut self, expr: &Expr) -> Result<Option<Object>, LangError> {
+        match expr {
+            Expr::Get(get) => self.get_link(get),
+            Expr::Index(index) => self.index_link(index),
+            Expr::Call(call) => self.call_link(call),
+            _ => self.evaluate(expr).map(Some),
+        }
+    }
+
+    fn get_link(&mut self, expr: &expr::Get) -> Result<Option<Object>, LangError> {
+        let object = match self.evaluate_link(&expr.object)? {
+            Some(object) if !(expr.optional && is_nil(&object)) => object,
+            _ => return Ok(None),
+        };
+        self.get_property(object, &expr.name).map(Some)
+    }
+
+    fn index_link(&mut self, expr: &expr::Index) -> Result<Option<Object>, LangError> {
+        let object = match self.evaluate_link(&expr.object)? {
+            Some(object) => object,
+            None => return Ok(None),
+        };
+        let index = self.evaluate(&expr.index)?;
+        if let Object::List(list) = &object {
+            return list_index(list, &index, &expr.bracket).map(Some);
+        }
+        if let Object::Instance(instance) = object {
+            if let Some(ret) =
+                self.call_special_method(&instance, "__index", vec![index], &expr.bracket)?
+            {
+                return Ok(Some(ret));
+            }
+        }
+        Err(LangError::RuntimeError {
+            message: "Only lists and instances with an '__index' method can be indexed."
+                .to_string(),
+            line: expr.bracket.line,
+        })
+    }
+
+    fn call_link(&mut self, expr: &expr::Call) -> Result<Option<Object>, LangError> {
+        let callee = match self.evaluate_link(&expr.callee)? {
+            Some(callee) => callee,
+            None => return Ok(None),
+        };
+        let arguments = self.evaluate_arguments(expr)?;
+        self.call_object(callee, arguments, &expr.paren).map(Some)
+    }
+
+    fn evaluate_arguments(&mut self, expr: &expr::Call) -> Result<Arguments, LangError> {
+        let mut arguments = Arguments::default();
+        let positional_count = expr.arguments.len() - expr.names.len();
+        for arg in &expr.arguments[..positional_count] {
+            let evaluated_arg = self.evaluate(arg)?;
+            arguments.positional.push(evaluated_arg);
+        }
+        for (name, arg) in expr.names.iter().zip(&expr.arguments[positional_count..]) {
+            let evaluated_arg = self.evaluate(arg)?;
+            arguments.named.push((name.clone(), evaluated_arg));
+        }
+        Ok(arguments)
+    }
+
+    fn define_methods(&self, declarations: &[Rc<stmt::Function>]) -> HashMap<String, LoxFunction> {
+        declarations
+            .iter()
+            .map(|method| {
+                let function = LoxFunction::new(method.clone(), self.environment.clone(), false);
+                (method.name.lexeme.clone(), function)
+            })
+            .collect()
+    }
+
+    /// Collect the methods of the traits a class uses. Two traits providing
+    /// the same method is an error unless the class overrides it.
+    fn merge_traits(
+        &mut self,
+        class: &stmt::Class,
+    ) -> Result<HashMap<String, LoxFunction>, LangError> {
+        let mut methods = HashMap::new();
+        let mut providers: HashMap<String, String> = HashMap::new();
+        for trait_expr in class.traits.iter() {
+            let line = trait_expr.name.line;
+            let lox_trait = match self.look_up_variable(trait_expr.id, &trait_expr.name)? {
+                Object::Trait(lox_trait) => lox_trait,
+                _ => {
+                    let message = format!("'{}' is not a trait.", trait_expr.name.lexeme);
+                    return Err(LangError::RuntimeError { message, line });
+                }
+            };
+            for (name, method) in lox_trait.methods.iter() {
+                if class.methods.iter().any(|m| m.name.lexeme == *name) {
+                    continue;
+                }
+                if let Some(other) = providers.insert(name.clone(), lox_trait.name.clone()) {
+                    let message = format!(
+                        "Method '{}' is defined by both '{}' and '{}'.",
+                        name, other, lox_trait.name
+                    );
+                    return Err(LangError::RuntimeError { message, line });
+                }
+                methods.insert(name.clone(), method.clone());
+            }
+        }
+        Ok(methods)
+    }
+
+    /// Call a special method such as `__add` if the instance's class defines it
+    fn call_special_method(
+        &mut self,
+        instance: &Rc<LoxInstance>,
+        name: &str,
+        arguments: Vec<Object>,
+        token: &Token,
+    ) -> Result<Option<Object>, LangError> {
+        match instance.class.find_method(name) {
+            Some(method) => {
+                let method = method.bind(Object::Instance(instance.clone()));
+                let ret = self.call_callable(&method, arguments.into(), token)?;
+                Ok(Some(ret))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Compare with `==`, letting instances override it with `__eq` or `equals`.
+    /// Those only run against another instance, anything else is never equal.
+    fn objects_equal(
+        &mut self,
+        left: Object,
+        right: Object,
+        operator: &Token,
+    ) -> Result<bool, LangError> {
+        if let Object::Instance(instance) = &left {
+            if !matches!(right, Object::Instance(_)) {
+                return Ok(false);
+            }
+            for name in ["__eq", "equals"] {
+                let arguments = vec![right.clone()];
+                if let Some(ret) = self.call_special_method(instance, name, arguments, operator)? {
+                    return Ok(ret.is_truthy());
+                }
+            }
+        }
+        Ok(left.equals(&right))
+    }
+
+    /// Apply a binary operator to evaluated operands, also for compound assignments
+    fn binary_operation(
+        &mut self,
+        left: Object,
+        operator: &Token,
+        right: Object,
+    ) -> Result<Object, LangError> {
+        if let (Object::Instance(instance), Some(name)) =
+            (&left, special_method_name(&operator.token_type))
+        {
+            let arguments = vec![right.clone()];
+            if let Some(ret) = self.call_special_method(instance, name, arguments, operator)? {
+                return Ok(ret);
+            }
+        }
+        let (left, right) = match (&operator.token_type, left, right) {
+            (TokenType::Is, object, Object::Class(class)) => {
+                let is_instance = match object {
+                    Object::Instance(instance) => instance.class.is_subclass_of(&class),
+                    _ => false,
+                };
+                let value = literal_type::convert_bool_to_literal_bool(is_instance);
+                return Ok(Object::Value(value));
+            }
+            (TokenType::Is, _, _) => {
+                return Err(LangError::RuntimeError {
+                    message: "Right operand of 'is' must be a class.".to_string(),
+                    line: operator.line,
+                });
+            }
+            (TokenType::EqualEqual, left, right) => {
+                let equal = self.objects_equal(left, right, operator)?;
+                return Ok(Object::Value(literal_type::convert_bool_to_literal_bool(
+                    equal,
+                )));
+            }
+            (TokenType::BangEqual, left, right) => {
+                let equal = self.objects_equal(left, right, operator)?;
+                return Ok(Object::Value(literal_type::convert_bool_to_literal_bool(
+                    !equal,
+                )));
+            }
+            (_, Object::Value(left), Object::Value(right)) => (left, right),
+            (TokenType::Plus, _, _) => {
+                return Err(LangError::RuntimeError {
+                    message: "Operands must be numbers or strings.".to_string(),
+                    line: operator.line,
+                });
+            }
+            _ => {
+                return Err(LangError::RuntimeError {
+                    message: "Operands must be numbers.".to_string(),
+                    line: operator.line,
+                });
+            }
+        };
+        let value = match operator.token_type {
+            TokenType::Minus => left - right,
+            TokenType::Plus => left + right,
+            TokenType::Star => left * right,
+            TokenType::Slash => left / right,
+            TokenType::Percent => left % right,
+            TokenType::StarStar => literal_type::power(left, right),
+            TokenType::Ampersand => literal_type::bitwise::and(left, right),
+            TokenType::Pipe => literal_type::bitwise::or(left, right),
+            TokenType::Caret => literal_type::bitwise::xor(left, right),
+            TokenType::LessLess => literal_type::bitwise::shl(left, right),
+            TokenType::GreaterGreater => literal_type::bitwise::shr(left, right),
+            TokenType::Greater => literal_type::comparison::gt(left, right),
+            TokenType::GreaterEqual => literal_type::comparison::ge(left, right),
+            TokenType::Less => literal_type::comparison::lt(left, right),
+            TokenType::LessEqual => literal_type::comparison::le(left, right),
+            _ => panic!("invalid Expression"),
+        };
+        if let LiteralType::Error(message) = value {
             return Err(LangError::RuntimeError {
                 message,
-                line: token.line,
+                line: operator.line,
             });
         }
-        let ret = callable.call(self, arguments)?;
-        Ok(ret)
+        Ok(Object::Value(value))
+    }
+
+    /// Read a field or run a getter, also for compound assignments
+    fn get_property(&mut self, object: Object, name: &Token) -> Result<Object, LangError> {
+        match object {
+            Object::Instance(instance) => {
+                if !instance.has_field(&name.lexeme) {
+                    if let Some(getter) = instance.class.find_getter(&name.lexeme) {
+                        let getter = getter.bind(Object::Instance(instance));
+                        return self.call_callable(&getter, Arguments::default(), name);
+                    }
+                }
+                instance.get(name)
+            }
+            Object::Class(class) => class.get(name),
+            Object::Generator(generator) if ["next", "hasNext"].contains(&name.lexeme.as_str()) => {
+                Ok(Object::function(GeneratorMethod::new(
+                    generator,
+                    &name.lexeme,
+                )))
+            }
+            Object::List(list) if name.lexeme == "length" => {
+                let length = list.elements.borrow().len();
+                Ok(Object::Value(LiteralType::Int(length as i64)))
+            }
+            _ => Err(LangError::RuntimeError {
+                message: "Only instances and classes have properties.".to_string(),
+                line: name.line,
+            }),
+        }
+    }
+
+    fn assign_variable(&mut self, id: u64, name: &Token, value: Object) -> Result<(), LangError> {
+        if let Some(&(distance, slot)) = self.locals.get(&id) {
+            self.environment.assign_at(distance, slot, name, value)
+        } else {
+            self.globals.assign(name, value)
+        }
+    }
+
+    fn set_property(&self, object: Object, name: &Token, value: Object) -> Result<(), LangError> {
+        match object {
+            Object::Instance(instance) if instance.is_frozen() => {
+                return Err(LangError::RuntimeError {
+                    message: "Can't set a field on a frozen instance.".to_string(),
+                    line: name.line,
+                })
+            }
+            Object::Instance(instance) => instance.set(name, value),
+            Object::Class(class) => class.set(name, value),
+            _ => {
+                return Err(LangError::RuntimeError {
+                    message: "Only instances and classes have fields.".to_string(),
+                    line: name.line,
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// The integers a range starts and stops at
+    fn range_bounds(&mut self, expr: &expr::Range) -> Result<(i64, i64), LangError> {
+        let start = self.evaluate(&expr.start)?;
+        let end = self.evaluate(&expr.end)?;
+        match (start, end) {
+            (Object::Value(LiteralType::Int(start)), Object::Value(LiteralType::Int(end))) => {
+                Ok((start, end))
+            }
+            _ => Err(LangError::RuntimeError {
+                message: "Range bounds must be integers.".to_string(),
+                line: expr.operator.line,
+            }),
+        }
+    }
+
+    /// Start a `for-in` loop. A range written in the loop is counted through
+    /// without building the list it would evaluate to.
+    pub fn iteration(&mut self, iterable: &Expr, keyword: &Token) -> Result<Iteration, LangError> {
+        if let Expr::Range(range) = iterable {
+            let (start, end) = self.range_bounds(range)?;
+            return Ok(Iteration::Range(start, end));
+        }
+        let value = self.evaluate(iterable)?;
+        self.iterate(value, keyword)
+    }
+
+    /// Lists, the characters of strings, and instances that either have an
+    /// `iter()` method or are iterators themselves
+    fn iterate(&mut self, value: Object, keyword: &Token) -> Result<Iteration, LangError> {
+        match value {
+            Object::List(list) => return Ok(Iteration::List(list, 0)),
+            Object::Generator(generator) => return Ok(Iteration::Generator(generator)),
+            Object::Value(LiteralType::Str(string)) => {
+                let chars: Vec<char> = string.chars().collect();
+                return Ok(Iteration::Chars(chars.into_iter()));
+            }
+            Object::Instance(instance) => {
+                match self.call_special_method(&instance, "iter", Vec::new(), keyword)? {
+                    Some(Object::Instance(iterator)) => return Ok(Iteration::Iterator(iterator)),
+                    Some(value) => return self.iterate(value, keyword),
+                    None if instance.class.find_method("hasNext").is_some() => {
+                        return Ok(Iteration::Iterator(instance))
+                    }
+                    None => (),
+                }
+            }
+            _ => (),
+        }
+        Err(LangError::RuntimeError {
+            message: "Can only iterate over lists, strings, ranges and iterators.".to_string(),
+            line: keyword.line,
+        })
+    }
+
+    /// The next element of a `for-in` loop, or `None` once there are no more
+    pub fn next_element(
+        &mut self,
+        iteration: &mut Iteration,
+        keyword: &Token,
+    ) -> Result<Option<Object>, LangError> {
+        let iterator = match iteration {
+            Iteration::List(list, index) => {
+                let element = list.elements.borrow().get(*index).cloned();
+                *index += 1;
+                return Ok(element);
+            }
+            Iteration::Chars(chars) => {
+                let element = chars
+                    .next()
+                    .map(|c| Object::Value(LiteralType::Str(c.to_string())));
+                return Ok(element);
+            }
+            Iteration::Range(next, end) => {
+                if next >= end {
+                    return Ok(None);
+                }
+                *next += 1;
+                return Ok(Some(Object::Value(LiteralType::Int(*next - 1))));
+            }
+            Iteration::Generator(generator) => {
+                return generator.clone().advance(self, keyword.line)
+            }
+            Iteration::Iterator(iterator) => iterator.clone(),
+        };
+        let missing_method = || LangError::RuntimeError {
+            message: "Iterators need 'hasNext' and 'next' methods.".to_string(),
+            line: keyword.line,
+        };
+        let has_next = self
+            .call_special_method(&iterator, "hasNext", Vec::new(), keyword)?
+            .ok_or_else(missing_method)?;
+        if !has_next.is_truthy() {
+            return Ok(None);
+        }
+        self.call_special_method(&iterator, "next", Vec::new(), keyword)?
+            .ok_or_else(missing_method)
+            .map(Some)
+    }
+
+    /// The index of the first arm of a `match` that takes its value, with the
+    /// environment holding the arm's bindings. The resolver makes sure the
+    /// last arm matches anything.
+    pub fn match_arm(
+        &mut self,
+        stmt: &stmt::Match,
+    ) -> Result<Option<(usize, Rc<Environment>)>, LangError> {
+        let value = self.evaluate(&stmt.value)?;
+        for (index, arm) in stmt.arms.iter().enumerate() {
+            let mut bindings = Vec::new();
+            if !self.match_pattern(&arm.pattern, &value, &mut bindings)? {
+                continue;
+            }
+            let environment = Environment::new(Some(self.environment.clone()));
+            for (name, value) in bindings {
+                environment.define(&name.lexeme, value);
+            }
+            if let Some(guard) = &arm.guard {
+                if !self.evaluate_in(guard, environment.clone())?.is_truthy() {
+                    continue;
+                }
+            }
+            return Ok(Some((index, environment)));
+        }
+        Ok(None)
+    }
+
+    /// The elements of a list being destructured into `count` targets
+    fn unpack(&self, value: Object, count: usize, line: u32) -> Result<Vec<Object>, LangError> {
+        let elements = match value {
+            Object::List(list) => list.elements.borrow().clone(),
+            _ => {
+                return Err(LangError::RuntimeError {
+                    message: "Only lists can be unpacked.".to_string(),
+                    line,
+                })
+            }
+        };
+        if elements.len() != count {
+            let message = format!(
+                "Expected {} values to unpack but got {}.",
+                count,
+                elements.len()
+            );
+            return Err(LangError::RuntimeError { message, line });
+        }
+        Ok(elements)
+    }
+
+    /// Whether `value` matches `pattern`, pushing what it binds in the order
+    /// the resolver declared the names
+    fn match_pattern<'p>(
+        &mut self,
+        pattern: &'p Pattern,
+        value: &Object,
+        bindings: &mut Vec<(&'p Token, Object)>,
+    ) -> Result<bool, LangError> {
+        match pattern {
+            Pattern::Wildcard => Ok(true),
+            Pattern::Literal(literal) => {
+                Ok(matches!(value, Object::Value(value) if value == literal))
+            }
+            Pattern::Binding(name) => {
+                bindings.push((name, value.clone()));
+                Ok(true)
+            }
+            Pattern::Alternatives(alternatives) => {
+                for alternative in alternatives {
+                    if self.match_pattern(alternative, value, bindings)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Pattern::Instance {
+                class,
+                positional,
+                named,
+            } => {
+                let class_name = &class.name;
+                let class = match self.look_up_variable(class.id, class_name)? {
+                    Object::Class(class) => class,
+                    _ => {
+                        return Err(LangError::RuntimeError {
+                            message: format!(
+                                "'{}' in a pattern is not a class.",
+                                class_name.lexeme
+                            ),
+                            line: class_name.line,
+                        })
+                    }
+                };
+                let instance = match value {
+                    Object::Instance(instance) if instance.class.is_subclass_of(&class) => instance,
+                    _ => return Ok(false),
+                };
+                let params = class
+                    .find_method("init")
+                    .map_or(Vec::new(), |init| init.params().to_vec());
+                if positional.len() > params.len() {
+                    let message = format!(
+                        "Pattern has {} positional fields but '{}' takes {} in 'init'.",
+                        positional.len(),
+                        class_name.lexeme,
+                        params.len()
+                    );
+                    return Err(LangError::RuntimeError {
+                        message,
+                        line: class_name.line,
+                    });
+                }
+                let fields = params
+                    .iter()
+                    .zip(positional)
+                    .chain(named.iter().map(|(f, p)| (f, p)));
+                for (field, pattern) in fields {
+                    if !instance.has_field(&field.lexeme) {
+                        return Ok(false);
+                    }
+                    let field = instance.clone().get(field)?;
+                    if !self.match_pattern(pattern, &field, bindings)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// The value to store and the value of the expression for a compound
+    /// assignment or increment of a target currently holding `current`
+    fn update(
+        &mut self,
+        current: Object,
+        operator: &Token,
+        value: &Expr,
+        postfix: bool,
+    ) -> Result<(Object, Object), LangError> {
+        let value = self.evaluate(value)?;
+        let updated = self.binary_operation(current.clone(), operator, value)?;
+        let result = if postfix { current } else { updated.clone() };
+        Ok((updated, result))
+    }
+
+    pub fn stringify(&mut self, object: Object, token: &Token) -> Result<String, LangError> {
+        let string = match object {
+            Object::Value(value) => value.to_string(),
+            Object::Function(func) => func.to_string(),
+            Object::Class(class) => class.to_string(),
+            Object::Trait(lox_trait) => lox_trait.name.clone(),
+            Object::Generator(generator) => format!("generator <{}>", generator.name()),
+            Object::List(list) => {
+                let elements = list.elements.borrow().clone();
+                let mut strings = Vec::new();
+                for element in elements {
+                    strings.push(self.stringify(element, token)?);
+                }
+                format!("[{}]", strings.join(", "))
+            }
+            Object::Instance(instance) => {
+                match self.call_special_method(&instance, "__str", Vec::new(), token)? {
+                    Some(ret) => self.stringify(ret, token)?,
+                    None => instance.to_string(),
+                }
+            }
+        };
+        Ok(string)
     }
 }
 
-impl stmt::Visitor<Result<(), LangError>> for Interpreter {
-    fn visit_block_stmt(&mut self, stmt: &stmt::Block) -> Result<(), LangError> {
+impl stmt::Visitor<Result<ExecResult, LangError>> for Interpreter {
+    fn visit_block_stmt(&mut self, stmt: &stmt::Block) -> Result<ExecResult, LangError> {
         let previous_environment = Some(self.environment.clone());
-        self.execute_block(&stmt.statements, Environment::new(previous_environment))?;
-        Ok(())
+        self.execute_block(&stmt.statements, Environment::new(previous_environment))
+    }
+
+    fn visit_break_stmt(&mut self, _stmt: &stmt::Break) -> Result<ExecResult, LangError> {
+        Ok(ExecResult::Break)
     }
 
-    fn visit_class_stmt(&mut self, stmt: &stmt::Class) -> Result<(), LangError> {
-        let superclass = if let Some(superclass_expr) = stmt.superclass.clone() {
-            let object = self.evaluate(&Box::new(Expr::Variable(superclass_expr.clone())))?;
+    fn visit_continue_stmt(&mut self, _stmt: &stmt::Continue) -> Result<ExecResult, LangError> {
+        Ok(ExecResult::Continue)
+    }
+
+    fn visit_class_stmt(&mut self, stmt: &stmt::Class) -> Result<ExecResult, LangError> {
+        self.environment.check_redeclare(&stmt.name)?;
+        let superclass = if let Some(superclass_expr) = &stmt.superclass {
+            let object = self.look_up_variable(superclass_expr.id, &superclass_expr.name)?;
             match object {
-                Object::Class(class) => Some(Box::new(class)),
+                Object::Class(class) => Some(class),
                 _ => {
                     return Err(LangError::RuntimeError {
                         message: "Superclass must be a class.".to_string(),
@@ -118,266 +951,967 @@ impl stmt::Visitor<Result<(), LangError>> for Interpreter {
         } else {
             None
         };
-        self.environment
-            .define(stmt.name.lexeme.clone(), Object::Value(LiteralType::Nil));
+        let mut methods = self.merge_traits(stmt)?;
         if let Some(superclass) = superclass.clone() {
             self.environment = Environment::new(Some(self.environment.clone()));
-            self.environment
-                .define("super".to_string(), Object::Class(*superclass));
+            self.environment.define("super", Object::Class(superclass));
         }
-        let mut methods = HashMap::new();
         for method in stmt.methods.iter() {
-            let is_initializer = method.clone().name.lexeme == "init".to_string();
+            let is_initializer = method.name.lexeme == "init";
             let function =
                 LoxFunction::new(method.clone(), self.environment.clone(), is_initializer);
-            methods.insert(method.clone().name.lexeme, function);
+            methods.insert(method.name.lexeme.clone(), function);
         }
-        let class = LoxClass::new(stmt.name.lexeme.clone(), superclass.clone(), methods);
-        if let Some(_) = superclass {
+        let class_methods = self.define_methods(&stmt.class_methods);
+        let getters = self.define_methods(&stmt.getters);
+        let class = Object::Class(LoxClass::new(
+            stmt.name.lexeme.clone(),
+            superclass.clone(),
+            methods,
+            class_methods,
+            getters,
+        ));
+        if superclass.is_some() {
             self.environment = self.environment.enclosing.clone().unwrap().clone();
         }
-        self.environment.assign(stmt.name.clone(), class)?;
-        Ok(())
+        // Methods only look the class up once they're called, by which time
+        // it has taken the slot the resolver gave it
+        self.environment.define(&stmt.name.lexeme, class);
+        Ok(ExecResult::Normal)
     }
 
-    fn visit_expression_stmt(&mut self, stmt: &stmt::Expression) -> Result<(), LangError> {
-        self.evaluate(&Box::new(stmt.clone().expression))?;
-        Ok(())
+    fn visit_expression_stmt(&mut self, stmt: &stmt::Expression) -> Result<ExecResult, LangError> {
+        self.evaluate(&stmt.expression)?;
+        Ok(ExecResult::Normal)
     }
 
-    fn visit_function_stmt(&mut self, stmt: &stmt::Function) -> Result<(), LangError> {
-        let identifier = stmt.clone().name.lexeme;
+    fn visit_function_stmt(&mut self, stmt: &Rc<stmt::Function>) -> Result<ExecResult, LangError> {
+        self.environment.check_redeclare(&stmt.name)?;
         let lox_function = LoxFunction::new(stmt.clone(), self.environment.clone(), false);
         self.environment
-            .define(identifier.clone(), Object::Function(Box::new(lox_function)));
-        Ok(())
+            .define(&stmt.name.lexeme, Object::function(lox_function));
+        Ok(ExecResult::Normal)
     }
 
-    fn visit_if_stmt(&mut self, stmt: &stmt::If) -> Result<(), LangError> {
-        let condition = self
-            .evaluate(&Box::new(stmt.condition.clone()))?
-            .fetch_value();
-        if literal_type::is_truthy(condition) {
-            self.execute(&stmt.then_statement)?;
-        } else if let Some(else_statement) = stmt.else_statement.clone() {
-            self.execute(&else_statement)?;
+    fn visit_if_stmt(&mut self, stmt: &stmt::If) -> Result<ExecResult, LangError> {
+        let condition = self.evaluate(&stmt.condition)?;
+        if condition.is_truthy() {
+            self.execute(&stmt.then_statement)
+        } else if let Some(else_statement) = &stmt.else_statement {
+            self.execute(else_statement)
+        } else {
+            Ok(ExecResult::Normal)
         }
-        Ok(())
     }
 
-    fn visit_print_stmt(&mut self, stmt: &stmt::Print) -> Result<(), LangError> {
-        let value = self.evaluate(&Box::new(stmt.clone().expression))?;
-        println!("{}", stringify_object(value));
-        Ok(())
+    fn visit_match_stmt(&mut self, stmt: &stmt::Match) -> Result<ExecResult, LangError> {
+        match self.match_arm(stmt)? {
+            Some((arm, environment)) => {
+                let body = std::slice::from_ref(&*stmt.arms[arm].body);
+                self.execute_block(body, environment)
+            }
+            None => Ok(ExecResult::Normal),
+        }
     }
 
-    fn visit_return_stmt(&mut self, stmt: &stmt::Return) -> Result<(), LangError> {
-        let value = if let Expr::Literal(literal) = stmt.clone().value {
-            Ok(Object::Value(literal.value))
-        } else {
-            self.evaluate(&Box::new(stmt.clone().value))
-        }?;
+    fn visit_multiassign_stmt(
+        &mut self,
+        stmt: &stmt::MultiAssign,
+    ) -> Result<ExecResult, LangError> {
+        let value = self.evaluate(&stmt.value)?;
+        let values = self.unpack(value, stmt.targets.len(), stmt.equals.line)?;
+        for (target, value) in stmt.targets.iter().zip(values) {
+            match target {
+                Expr::Variable(variable) => {
+                    self.assign_variable(variable.id, &variable.name, value)?
+                }
+                Expr::Get(get) => {
+                    let object = self.evaluate(&get.object)?;
+                    self.set_property(object, &get.name, value)?;
+                }
+                // The parser only lets variables and fields through
+                _ => (),
+            }
+        }
+        Ok(ExecResult::Normal)
+    }
 
-        Err(LangError::Return(value))
+    fn visit_print_stmt(&mut self, stmt: &stmt::Print) -> Result<ExecResult, LangError> {
+        let value = self.evaluate(&stmt.expression)?;
+        let string = self.stringify(value, &stmt.keyword)?;
+        println!("{}", string);
+        Ok(ExecResult::Normal)
     }
 
-    fn visit_var_stmt(&mut self, stmt: &stmt::Var) -> Result<(), LangError> {
-        let cloned_stmt = stmt.clone();
-        let value = self.evaluate(&Box::new(cloned_stmt.initializer))?;
-        self.environment.define(cloned_stmt.name.lexeme, value);
-        Ok(())
+    fn visit_return_stmt(&mut self, stmt: &stmt::Return) -> Result<ExecResult, LangError> {
+        if let Expr::Call(call) = &stmt.value {
+            let callee = match self.evaluate_link(&call.callee)? {
+                Some(callee) => callee,
+                None => return Ok(ExecResult::Return(Object::Value(LiteralType::Nil))),
+            };
+            let arguments = self.evaluate_arguments(call)?;
+            return Ok(ExecResult::TailCall(Box::new(TailCall {
+                callee,
+                arguments,
+                paren: call.paren.clone(),
+            })));
+        }
+        let value = self.evaluate(&stmt.value)?;
+        Ok(ExecResult::Return(value))
+    }
+
+    fn visit_trait_stmt(&mut self, stmt: &stmt::Trait) -> Result<ExecResult, LangError> {
+        self.environment.check_redeclare(&stmt.name)?;
+        let methods = self.define_methods(&stmt.methods);
+        let lox_trait = LoxTrait::new(stmt.name.lexeme.clone(), methods);
+        self.environment
+            .define(&stmt.name.lexeme, Object::Trait(lox_trait));
+        Ok(ExecResult::Normal)
+    }
+
+    fn visit_var_stmt(&mut self, stmt: &stmt::Var) -> Result<ExecResult, LangError> {
+        for name in stmt.names.iter() {
+            self.environment.check_redeclare(name)?;
+        }
+        let value = self.evaluate(&stmt.initializer)?;
+        let line = stmt.names[0].line;
+        let values = match stmt.pattern {
+            VarPattern::Name => vec![value],
+            VarPattern::List => self.unpack(value, stmt.names.len(), line)?,
+            VarPattern::Fields => {
+                let mut values = Vec::new();
+                for name in stmt.names.iter() {
+                    values.push(self.get_property(value.clone(), name)?);
+                }
+                values
+            }
+        };
+        for (name, value) in stmt.names.iter().zip(values) {
+            if stmt.constant {
+                self.environment.define_constant(&name.lexeme, value);
+            } else {
+                self.environment.define(&name.lexeme, value);
+            }
+        }
+        Ok(ExecResult::Normal)
+    }
+
+    fn visit_forin_stmt(&mut self, stmt: &stmt::ForIn) -> Result<ExecResult, LangError> {
+        let mut iteration = self.iteration(&stmt.iterable, &stmt.keyword)?;
+        while let Some(element) = self.next_element(&mut iteration, &stmt.keyword)? {
+            self.check_cancelled(stmt.keyword.line)?;
+            let environment = Environment::new(Some(self.environment.clone()));
+            environment.define(&stmt.name.lexeme, element);
+            let body = std::slice::from_ref(&*stmt.body);
+            match self.execute_block(body, environment)? {
+                ExecResult::Normal | ExecResult::Continue => (),
+                ExecResult::Break => break,
+                result => return Ok(result),
+            }
+        }
+        Ok(ExecResult::Normal)
     }
 
-    fn visit_while_stmt(&mut self, stmt: &stmt::While) -> Result<(), LangError> {
+    fn visit_while_stmt(&mut self, stmt: &stmt::While) -> Result<ExecResult, LangError> {
         loop {
-            let condition = self
-                .evaluate(&Box::new(stmt.condition.clone()))?
-                .fetch_value();
-            if !literal_type::is_truthy(condition) {
+            self.check_cancelled(stmt.keyword.line)?;
+            let condition = self.evaluate(&stmt.condition)?;
+            if !condition.is_truthy() {
                 break;
             }
-            self.execute(&stmt.body)?
+            match self.execute(&stmt.body)? {
+                ExecResult::Normal | ExecResult::Continue => (),
+                ExecResult::Break => break,
+                result => return Ok(result),
+            }
+            if let Some(increment) = &stmt.increment {
+                self.evaluate(increment)?;
+            }
         }
-        Ok(())
+        Ok(ExecResult::Normal)
+    }
+
+    fn visit_yield_stmt(&mut self, stmt: &stmt::Yield) -> Result<ExecResult, LangError> {
+        let value = self.evaluate(&stmt.value)?;
+        Ok(ExecResult::Yield(value))
     }
 }
 
 impl expr::Visitor<Result<Object, LangError>> for Interpreter {
     fn visit_binary_expr(&mut self, expr: &Binary) -> Result<Object, LangError> {
-        let left = self.evaluate(&expr.left)?.fetch_value();
-        let right = self.evaluate(&expr.right)?.fetch_value();
-        let value = match expr.operator.token_type {
-            TokenType::Minus => left - right,
-            TokenType::Plus => left + right,
-            TokenType::Star => left * right,
-            TokenType::Slash => left / right,
-            TokenType::Greater => literal_type::comparison::gt(left, right),
-            TokenType::GreaterEqual => literal_type::comparison::ge(left, right),
-            TokenType::Less => literal_type::comparison::lt(left, right),
-            TokenType::LessEqual => literal_type::comparison::le(left, right),
-            TokenType::BangEqual => literal_type::convert_bool_to_literal_bool(left != right),
-            TokenType::EqualEqual => literal_type::convert_bool_to_literal_bool(left == right),
-            _ => panic!("invalid Expression"),
-        };
-        if let LiteralType::Error(message) = value {
-            return Err(LangError::RuntimeError {
-                message,
-                line: expr.clone().operator.line,
-            });
-        }
-        Ok(Object::Value(value))
+        let left = self.evaluate(&expr.left)?;
+        let right = self.evaluate(&expr.right)?;
+        self.binary_operation(left, &expr.operator, right)
     }
 
     fn visit_call_expr(&mut self, expr: &expr::Call) -> Result<Object, LangError> {
-        let callee = self.evaluate(&expr.callee)?;
-        let mut arguments = Vec::new();
-        for arg in &expr.arguments {
-            let evaluated_arg = self.evaluate(&Box::new(arg.clone()))?.fetch_value();
-            arguments.push(evaluated_arg);
-        }
-        match callee {
-            Object::Class(class) => {
-                self.call_callable(Box::new(class), arguments, expr.clone().paren)
-            }
-            Object::Function(func) => self.call_callable(func, arguments, expr.clone().paren),
-            _ => {
-                return Err(LangError::RuntimeError {
-                    message: "Can only call functions and classes.".to_string(),
-                    line: expr.clone().paren.line,
-                });
-            }
+        Ok(self
+            .call_link(expr)?
+            .unwrap_or(Object::Value(LiteralType::Nil)))
+    }
+
+    fn visit_conditional_expr(&mut self, expr: &expr::Conditional) -> Result<Object, LangError> {
+        if self.evaluate(&expr.condition)?.is_truthy() {
+            self.evaluate(&expr.then_branch)
+        } else {
+            self.evaluate(&expr.else_branch)
         }
     }
 
     fn visit_get_expr(&mut self, expr: &expr::Get) -> Result<Object, LangError> {
-        let object = self.evaluate(&expr.object)?;
-        let name = expr.name.clone();
-        match object {
-            Object::Instance(instance) => Ok(instance.clone().get(name)?),
-            _ => Err(LangError::RuntimeError {
-                message: "Only instances have properties.".to_string(),
-                line: name.line,
-            }),
-        }
+        Ok(self
+            .get_link(expr)?
+            .unwrap_or(Object::Value(LiteralType::Nil)))
     }
 
     fn visit_grouping_expr(&mut self, expr: &Grouping) -> Result<Object, LangError> {
         self.evaluate(&expr.expression)
     }
 
+    fn visit_index_expr(&mut self, expr: &expr::Index) -> Result<Object, LangError> {
+        Ok(self
+            .index_link(expr)?
+            .unwrap_or(Object::Value(LiteralType::Nil)))
+    }
+
     fn visit_logical_expr(&mut self, expr: &expr::Logical) -> Result<Object, LangError> {
-        let left = self.evaluate(&expr.left)?.fetch_value();
-        if let TokenType::Or = expr.operator.token_type {
-            if literal_type::is_truthy(left.clone()) {
-                return Ok(Object::Value(left));
-            }
-        } else {
-            if !literal_type::is_truthy(left.clone()) {
-                return Ok(Object::Value(left));
-            }
+        let left = self.evaluate(&expr.left)?;
+        match expr.operator.token_type {
+            TokenType::Or if left.is_truthy() => return Ok(left),
+            TokenType::And if !left.is_truthy() => return Ok(left),
+            TokenType::QuestionQuestion if !is_nil(&left) => return Ok(left),
+            _ => (),
         }
 
         self.evaluate(&expr.right)
     }
 
+    fn visit_range_expr(&mut self, expr: &expr::Range) -> Result<Object, LangError> {
+        let (start, end) = self.range_bounds(expr)?;
+        let elements = (start..end)
+            .map(|n| Object::Value(LiteralType::Int(n)))
+            .collect();
+        Ok(Object::List(LoxList::new(elements)))
+    }
+
     fn visit_set_expr(&mut self, expr: &expr::Set) -> Result<Object, LangError> {
         let object = self.evaluate(&expr.object)?;
-        let name = expr.name.clone();
-        match object {
-            Object::Instance(instance) => {
+        if !matches!(object, Object::Instance(_) | Object::Class(_)) {
+            return Err(LangError::RuntimeError {
+                message: "Only instances and classes have fields.".to_string(),
+                line: expr.name.line,
+            });
+        }
+        let (value, result) = match &expr.operator {
+            Some(operator) => {
+                let current = self.get_property(object.clone(), &expr.name)?;
+                self.update(current, operator, &expr.value, expr.postfix)?
+            }
+            None => {
                 let value = self.evaluate(&expr.value)?;
-                instance.set(name, value.clone());
-                Ok(value)
+                (value.clone(), value)
             }
-            _ => Err(LangError::RuntimeError {
-                message: "Only instances have fields.".to_string(),
-                line: name.line,
-            }),
-        }
+        };
+        self.set_property(object, &expr.name, value)?;
+        Ok(result)
     }
 
     fn visit_super_expr(&mut self, expr: &expr::Super) -> Result<Object, LangError> {
-        let distance = self.locals.get(&expr.keyword.id).unwrap();
-        let keyword = expr.keyword.clone();
+        let (distance, slot) = self.locals[&expr.id];
+        let keyword = &expr.keyword;
         let superclass = self
             .environment
-            .get_at(*distance, keyword.lexeme, keyword.line)?;
+            .get_at(distance, slot, &keyword.lexeme, keyword.line)?;
+        // `this` is the only variable in the scope just inside `super`'s
         let object = self
             .environment
-            .get_at(*distance - 1, "this".to_string(), keyword.line)?;
-        let method = if let Object::Class(class) = superclass {
-            match class.find_method(expr.clone().method.lexeme) {
-                Some(method) => method,
-                None => {
-                    let message = format!("Undefined property '{}' .", expr.method.lexeme);
-                    return Err(LangError::RuntimeError {
-                        message,
-                        line: expr.method.line,
-                    });
-                }
+            .get_at(distance - 1, 0, "this", keyword.line)?;
+        let class = match superclass {
+            Object::Class(class) => class,
+            _ => {
+                return Err(LangError::RuntimeError {
+                    message: "Expected class.".to_string(),
+                    line: expr.method.line,
+                })
             }
-        } else {
-            return Err(LangError::RuntimeError {
-                message: "Expected class.".to_string(),
-                line: expr.method.line,
-            });
         };
-        if let Object::Instance(instance) = object {
-            Ok(Object::Function(Box::new(method.bind(instance))))
+        let name = &expr.method.lexeme;
+        let method = if let Object::Class(_) = object {
+            class.find_class_method(name)
         } else {
-            Err(LangError::RuntimeError {
-                message: "Expected class.".to_string(),
+            if let Some(getter) = class.find_getter(name) {
+                let getter = getter.bind(object);
+                return self.call_callable(&getter, Arguments::default(), &expr.method);
+            }
+            class.find_method(name)
+        };
+        match method {
+            Some(method) => Ok(Object::function(method.bind(object))),
+            None => Err(LangError::RuntimeError {
+                message: format!("Undefined property '{}'.", name),
                 line: expr.method.line,
-            })
+            }),
         }
     }
 
     fn visit_this_expr(&mut self, expr: &expr::This) -> Result<Object, LangError> {
-        self.look_up_variable(expr.keyword.clone())
+        self.look_up_variable(expr.id, &expr.keyword)
     }
 
     fn visit_unary_expr(&mut self, expr: &Unary) -> Result<Object, LangError> {
-        let right = self.evaluate(&expr.right)?.fetch_value();
-        let value = match expr.operator.token_type {
-            TokenType::Minus => -right,
-            TokenType::Bang => !right,
+        let right = self.evaluate(&expr.right)?;
+        if let (TokenType::Minus, Object::Instance(instance)) = (&expr.operator.token_type, &right)
+        {
+            if let Some(ret) =
+                self.call_special_method(instance, "__neg", Vec::new(), &expr.operator)?
+            {
+                return Ok(ret);
+            }
+        }
+        let value = match (&expr.operator.token_type, right) {
+            (TokenType::Bang, right) => {
+                literal_type::convert_bool_to_literal_bool(!right.is_truthy())
+            }
+            (TokenType::Minus, Object::Value(right)) => -right,
+            (TokenType::Minus, _) => LiteralType::Error("Operand must be a number.".to_string()),
+            (TokenType::Tilde, Object::Value(right)) => literal_type::bitwise::not(right),
+            (TokenType::Tilde, _) => LiteralType::Error("Operand must be an integer.".to_string()),
             _ => panic!("invalid Expression"),
         };
         if let LiteralType::Error(message) = value {
             return Err(LangError::RuntimeError {
                 message,
-                line: expr.clone().operator.line,
+                line: expr.operator.line,
             });
         }
         Ok(Object::Value(value))
     }
 
+    fn visit_lambda_expr(&mut self, expr: &expr::Lambda) -> Result<Object, LangError> {
+        let declaration = expr.function.clone();
+        let lox_function = LoxFunction::new(declaration, self.environment.clone(), false);
+        Ok(Object::function(lox_function))
+    }
+
     fn visit_literal_expr(&mut self, expr: &Literal) -> Result<Object, LangError> {
         let value = Object::Value(expr.value.clone());
         Ok(value)
     }
 
     fn visit_variable_expr(&mut self, expr: &expr::Variable) -> Result<Object, LangError> {
-        self.look_up_variable(expr.name.clone())
+        self.look_up_variable(expr.id, &expr.name)
     }
 
     fn visit_assign_expr(&mut self, expr: &expr::Assign) -> Result<Object, LangError> {
-        let value = self.evaluate(&expr.value)?;
-        if let Some(distance) = self.locals.get(&expr.name.id) {
-            self.environment
-                .assign_at(*distance, expr.name.clone(), value.clone())?;
-        } else {
-            self.globals.assign(expr.clone().name, value.clone())?;
+        let (value, result) = match &expr.operator {
+            Some(operator) => {
+                let current = self.look_up_variable(expr.id, &expr.name)?;
+                self.update(current, operator, &expr.value, expr.postfix)?
+            }
+            None => {
+                let value = self.evaluate(&expr.value)?;
+                (value.clone(), value)
+            }
+        };
+        self.assign_variable(expr.id, &expr.name, value)?;
+        Ok(result)
+    }
+
+    fn visit_tuple_expr(&mut self, expr: &expr::Tuple) -> Result<Object, LangError> {
+        let mut elements = Vec::new();
+        for element in expr.elements.iter() {
+            elements.push(self.evaluate(element)?);
         }
-        Ok(value)
+        Ok(Object::List(LoxList::new(elements)))
     }
 }
 
-fn stringify_object(object: Object) -> String {
-    match object {
-        Object::Value(value) => value.to_string(),
-        Object::Function(func) => func.to_string(),
-        Object::Class(class) => class.to_string(),
-        Object::Instance(instance) => instance.to_string(),
+/// A limit hit by a block is reported on the line of the statement around it
+fn on_statement_line(error: LangError, statement: &Stmt) -> LangError {
+    match error {
+        LangError::LimitExceeded { limit, line: 0 } => LangError::LimitExceeded {
+            limit,
+            line: line::stmt_line(statement).unwrap_or(0),
+        },
+        error => error,
+    }
+}
+
+fn is_nil(object: &Object) -> bool {
+    matches!(object, Object::Value(LiteralType::Nil))
+}
+
+/// Whether more than `max` objects are alive, once cycles have been collected.
+/// Objects are counted, not their size, so long strings and lists count once.
+///
+/// Nothing happens until the tracked objects pass `max`. Most garbage has then
+/// already been freed by reference counting and only needs forgetting, so a
+/// full collection only runs when that isn't enough to get back under `max`.
+fn too_many_objects(max: usize) -> bool {
+    if gc::tracked() <= max || gc::prune() <= max {
+        return false;
+    }
+    gc::collect();
+    gc::tracked() > max
+}
+
+fn check_arity(
+    callable: &dyn LoxCallable,
+    arguments: &Arguments,
+    token: &Token,
+) -> Result<(), LangError> {
+    let arity = callable.arity();
+    if !arity.accepts(arguments.count()) {
+        let message = format!(
+            "Expected {} arguments but got {}.",
+            arity,
+            arguments.count()
+        );
+        return Err(LangError::RuntimeError {
+            message,
+            line: token.line,
+        });
+    }
+    Ok(())
+}
+
+/// The method an instance on the left of a binary operator can define to overload it
+fn special_method_name(token_type: &TokenType) -> Option<&'static str> {
+    let name = match token_type {
+        TokenType::Plus => "__add",
+        TokenType::Minus => "__sub",
+        TokenType::Star => "__mul",
+        TokenType::Slash => "__div",
+        TokenType::Percent => "__mod",
+        TokenType::StarStar => "__pow",
+        TokenType::Less => "__lt",
+        TokenType::LessEqual => "__le",
+        TokenType::Greater => "__gt",
+        TokenType::GreaterEqual => "__ge",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn list_index(list: &LoxList, index: &Object, bracket: &Token) -> Result<Object, LangError> {
+    let elements = list.elements.borrow();
+    let position = match index {
+        Object::Value(LiteralType::Int(n)) => *n,
+        _ => {
+            return Err(LangError::RuntimeError {
+                message: "List index must be an integer.".to_string(),
+                line: bracket.line,
+            })
+        }
+    };
+    if position < 0 || position >= elements.len() as i64 {
+        return Err(LangError::RuntimeError {
+            message: "List index out of range.".to_string(),
+            line: bracket.line,
+        });
+    }
+    Ok(elements[position as usize].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use crate::scanner::scanner::Scanner;
+
+    fn interpret(source: &str, limits: Limits) -> Result<Interpreter, LangError> {
+        let tokens = Scanner::new(source.to_string()).scan_tokens();
+        let statements = Parser::new(tokens).parse()?;
+        let mut interpreter = Interpreter::new();
+        interpreter.limits = limits;
+        Resolver::new(&mut interpreter).resolve_statements(&statements)?;
+        interpreter.interpret(statements)?;
+        Ok(interpreter)
+    }
+
+    fn run(source: &str, limits: Limits) -> Result<(), LangError> {
+        interpret(source, limits).map(|_| ())
+    }
+
+    /// Run `source` and show the global `name` the way `print` would. The
+    /// script can use `list(...)` to gather several values.
+    fn global(source: &str, name: &str) -> String {
+        let source = format!("fun list(...items) {{ return items; }}\n{}", source);
+        let mut interpreter = interpret(&source, Limits::default())
+            .ok()
+            .expect("the script should run");
+        let name = Token::new(TokenType::Identifier, name.to_string(), None, 0);
+        let value = interpreter
+            .globals
+            .get(&name)
+            .ok()
+            .expect("undefined global");
+        interpreter
+            .stringify(value, &name)
+            .ok()
+            .expect("the value should print")
+    }
+
+    fn exceeded(result: Result<(), LangError>) -> Option<(Limit, u32)> {
+        match result {
+            Err(LangError::LimitExceeded { limit, line }) => Some((limit, line)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let limits = Limits {
+            max_steps: Some(100),
+            ..Limits::default()
+        };
+        let result = run("var i = 0;\nwhile (true) {}", limits);
+        assert_eq!(exceeded(result), Some((Limit::Steps, 2)));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let limits = Limits {
+            timeout: Some(Duration::from_millis(10)),
+            ..Limits::default()
+        };
+        let result = run("while (true) {}", limits);
+        assert_eq!(exceeded(result), Some((Limit::Timeout, 1)));
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        let limits = Limits {
+            max_call_depth: 10,
+            ..Limits::default()
+        };
+        let result = run("fun f() { return 1 + f(); }\nf();", limits);
+        assert_eq!(exceeded(result), Some((Limit::CallDepth, 1)));
+    }
+
+    #[test]
+    fn test_cancel_from_another_thread() {
+        let tokens = Scanner::new("while (true) {}".to_string()).scan_tokens();
+        let statements = Parser::new(tokens).parse().ok().unwrap();
+        let mut interpreter = Interpreter::new();
+        let cancel = interpreter.cancel_handle();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            cancel.cancel();
+        });
+        let result = interpreter.interpret(statements);
+        canceller.join().unwrap();
+        assert!(matches!(result, Err(LangError::Interrupted { line: 1 })));
+    }
+
+    #[test]
+    fn test_object_limit_counts_live_objects() {
+        let limits = Limits {
+            max_objects: Some(100),
+            ..Limits::default()
+        };
+        let garbage =
+            "class A {}\nfor (var i = 0; i < 1000; i = i + 1) { var a = A(); a.self = a; }";
+        assert!(run(garbage, limits.clone()).is_ok());
+        let kept = "class A {}\nvar all = nil;\nfor (var i = 0; i < 1000; i = i + 1) { var a = A(); a.next = all; all = a; }";
+        assert_eq!(exceeded(run(kept, limits)), Some((Limit::Objects, 3)));
+    }
+
+    #[test]
+    fn test_object_limit_collects_in_batches() {
+        let limits = Limits {
+            max_objects: Some(100),
+            ..Limits::default()
+        };
+        let near_limit = "class A {}\nvar all = nil;\n\
+            for (var i = 0; i < 80; i = i + 1) { var a = A(); a.next = all; all = a; }\n\
+            for (var i = 0; i < 2000; i = i + 1) { var a = A(); a.self = a; }";
+        let before = gc::stats().collections;
+        assert!(run(near_limit, limits).is_ok());
+        assert!(gc::stats().collections - before < 200);
+    }
+
+    #[test]
+    fn test_object_limit_is_exact() {
+        let kept: Vec<Rc<LoxList>> = (0..150).map(|_| LoxList::new(Vec::new())).collect();
+        gc::collect();
+        let live = gc::tracked();
+        assert!(!too_many_objects(live));
+        assert!(too_many_objects(live - 1));
+        drop(kept);
+    }
+
+    #[test]
+    fn test_unpack_needs_matching_length() {
+        let source = "fun pair() { return 1, 2; }\nvar [a, b] = pair();\nvar [c] = pair();";
+        match run(source, Limits::default()) {
+            Err(LangError::RuntimeError { message, line }) => {
+                assert_eq!(message, "Expected 1 values to unpack but got 2.");
+                assert_eq!(line, 3);
+            }
+            _ => panic!("unpacking two values into one name should fail"),
+        }
+    }
+
+    #[test]
+    fn test_several_values_need_a_list_pattern() {
+        let source = "var [a, b] = 1, 2;\nvar result = list(a, b);";
+        assert_eq!(global(source, "result"), "[1, 2]");
+        assert!(matches!(
+            run("var x = 1, 2;", Limits::default()),
+            Err(LangError::ParseError)
+        ));
+    }
+
+    #[test]
+    fn test_assign_to_constant() {
+        let source = "const a = 1;\nfun f() { a = 2; }\nf();";
+        match run(source, Limits::default()) {
+            Err(LangError::RuntimeError { message, line }) => {
+                assert_eq!(message, "Can't assign to constant 'a'.");
+                assert_eq!(line, 2);
+            }
+            _ => panic!("assigning to a global constant should fail"),
+        }
+        for source in ["const c = 1;\nvar c = 2;", "const c = 1;\nconst c = 2;"] {
+            match run(source, Limits::default()) {
+                Err(LangError::RuntimeError { message, line }) => {
+                    assert_eq!(message, "Can't redeclare constant 'c'.");
+                    assert_eq!(line, 2);
+                }
+                _ => panic!("redeclaring a global constant should fail"),
+            }
+        }
+        let source = "{ const b = 1; b += 1; }";
+        assert!(matches!(
+            run(source, Limits::default()),
+            Err(LangError::ResolveError)
+        ));
+    }
+
+    #[test]
+    fn test_iterator_needs_next() {
+        let source = "class Once {\n  hasNext() { return true; }\n}\nfor (x in Once()) print x;";
+        match run(source, Limits::default()) {
+            Err(LangError::RuntimeError { message, line }) => {
+                assert_eq!(message, "Iterators need 'hasNext' and 'next' methods.");
+                assert_eq!(line, 4);
+            }
+            _ => panic!("an iterator without 'next' should fail"),
+        }
+    }
+
+    #[test]
+    fn test_generator_cant_resume_itself() {
+        let source =
+            "var g;\nfun f() {\n  yield 1;\n  g.next();\n}\ng = f();\ng.next();\ng.next();";
+        match run(source, Limits::default()) {
+            Err(LangError::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Generator is already running.");
+            }
+            _ => panic!("a generator resuming itself should fail"),
+        }
+    }
+
+    #[test]
+    fn test_is_checks_superclasses() {
+        let source = "class A {}\nclass B < A {}\nclass C {}\nvar b = B();\n\
+            var result = list(b is B, b is A, b is C, 1 is A);";
+        assert_eq!(global(source, "result"), "[true, true, false, false]");
+        assert!(run("var a = 1 is 2;", Limits::default()).is_err());
+    }
+
+    #[test]
+    fn test_instance_class() {
+        let source = "class A {}\nclass B < A {}\nvar b = B();\n\
+            var result = list(b.class, b.class == B, b.class() is A);";
+        assert_eq!(global(source, "result"), "[B, true, true]");
+    }
+
+    #[test]
+    fn test_bound_methods_compare_by_receiver() {
+        let source = "class A { m() {} }\nvar a = A();\nvar b = A();\n\
+            fun f() {}\nvar result = list(a.m == a.m, a.m == b.m, f == f, a.m == f);";
+        assert_eq!(global(source, "result"), "[true, false, true, false]");
+    }
+
+    #[test]
+    fn test_equals_method() {
+        let source = "class P {\n  init(x) { this.x = x; }\n  equals(other) { return this.x == other.x; }\n}\n\
+            var result = list(P(1) == P(1), P(1) != P(2), P(1) == P(2));";
+        assert_eq!(global(source, "result"), "[true, true, false]");
+    }
+
+    #[test]
+    fn test_equals_method_only_sees_instances() {
+        let source = "class P {\n  init(x) { this.x = x; }\n  equals(other) { return this.x == other.x; }\n}\n\
+            var result = list(P(1) == nil, P(1) != 1, nil == P(1));";
+        assert_eq!(global(source, "result"), "[false, true, false]");
+    }
+
+    #[test]
+    fn test_operators_call_special_methods() {
+        let source = "class V {\n  init(x) { this.x = x; }\n\
+            __add(other) { return V(this.x + other.x); }\n\
+            __lt(other) { return this.x < other.x; }\n\
+            __neg() { return V(-this.x); }\n\
+            __index(i) { return this.x * i; }\n\
+            __call(y) { return this.x + y; }\n\
+            __str() { return \"V(\" + this.x + \")\"; }\n\
+            __eq(other) { return this.x == other.x; }\n}\n\
+            var result = list(V(1) + V(2), V(1) < V(2), -V(3), V(2)[5], V(2)(3), V(4) == V(4));";
+        assert_eq!(global(source, "result"), "[V(3), true, V(-3), 10, 5, true]");
+    }
+
+    #[test]
+    fn test_operator_without_special_method() {
+        let result = run("class V {}\nprint V() + V();", Limits::default());
+        assert!(matches!(
+            result,
+            Err(LangError::RuntimeError { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_class_methods_and_fields() {
+        let source =
+            "class Counter {\n  class make() { this.made = this.made + 1; return this(); }\n}\n\
+            Counter.made = 0;\nCounter.make();\nCounter.make();\nvar result = Counter.made;";
+        assert_eq!(global(source, "result"), "2");
+    }
+
+    #[test]
+    fn test_getters() {
+        let source = "class Square {\n  init(side) { this.side = side; }\n  area { return this.side * this.side; }\n}\n\
+            class Cube < Square {\n  area { return 6 * super.area; }\n}\n\
+            var result = list(Square(3).area, Cube(2).area);";
+        assert_eq!(global(source, "result"), "[9, 24]");
+    }
+
+    #[test]
+    fn test_super_calls() {
+        let source = "class A {\n  name() { return \"A\"; }\n  class kind() { return \"a\"; }\n}\n\
+            class B < A {\n  name() { return super.name() + \"B\"; }\n  class kind() { return super.kind() + \"b\"; }\n}\n\
+            var result = list(B().name(), B.kind());";
+        assert_eq!(global(source, "result"), "[AB, ab]");
+        let result = run(
+            "class A {}\nclass B < A {\n  m() { return super.missing; }\n}\nB().m();",
+            Limits::default(),
+        );
+        match result {
+            Err(LangError::RuntimeError { message, line }) => {
+                assert_eq!(message, "Undefined property 'missing'.");
+                assert_eq!(line, 3);
+            }
+            _ => panic!("a missing superclass property should fail"),
+        }
+    }
+
+    #[test]
+    fn test_traits_add_methods() {
+        let source = "trait Named {\n  label() { return \"<\" + this.name + \">\"; }\n}\n\
+            trait Loud {\n  shout() { return this.label() + \"!\"; }\n}\n\
+            class Dog with Named, Loud {\n  init(name) { this.name = name; }\n}\n\
+            var result = Dog(\"rex\").shout();";
+        assert_eq!(global(source, "result"), "<rex>!");
+    }
+
+    #[test]
+    fn test_trait_conflicts() {
+        let traits = "trait A {\n  m() { return 1; }\n}\ntrait B {\n  m() { return 2; }\n}\n";
+        let conflict = format!("{}class C with A, B {{}}", traits);
+        assert!(matches!(
+            run(&conflict, Limits::default()),
+            Err(LangError::ResolveError)
+        ));
+        let repeated = format!("{}class C with A, A {{}}", traits);
+        assert!(matches!(
+            run(&repeated, Limits::default()),
+            Err(LangError::ResolveError)
+        ));
+        let overridden = format!(
+            "{}class C with A, B {{\n  m() {{ return 3; }}\n}}\nvar result = C().m();",
+            traits
+        );
+        assert_eq!(global(&overridden, "result"), "3");
+    }
+
+    #[test]
+    fn test_default_and_named_arguments() {
+        let source = "fun f(a, b = 2, c = 3) { return list(a, b, c); }\nvar result = f(1, c: 4);";
+        assert_eq!(global(source, "result"), "[1, 2, 4]");
+        let source = "fun f(a = 1, b) {}";
+        assert!(matches!(
+            run(source, Limits::default()),
+            Err(LangError::ParseError)
+        ));
+        let source = "fun f(a) {}\nf(a: 1, a: 2);";
+        assert!(matches!(
+            run(source, Limits::default()),
+            Err(LangError::ParseError)
+        ));
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let source = "var seen = 0;\nfor (var i = 0; i < 10; i = i + 1) {\n  \
+            if (i == 2) continue;\n  if (i == 5) break;\n  seen = seen * 10 + i;\n}\n\
+            var inner = 0;\nfor (a in 0..3) {\n  for (b in 0..3) {\n    \
+            if (b == 1) break;\n    inner = inner + 1;\n  }\n}\n\
+            fun find() {\n  while (true) {\n    for (x in 1..10) {\n      \
+            if (x == 3) return x;\n    }\n  }\n}\nvar result = list(seen, inner, find());";
+        assert_eq!(global(source, "result"), "[134, 3, 3]");
+        let source = "while (true) {\n  fun f() { break; }\n}";
+        assert!(matches!(
+            run(source, Limits::default()),
+            Err(LangError::ResolveError)
+        ));
+    }
+
+    #[test]
+    fn test_shared_function_bodies() {
+        let source = "fun fib(n) {\n  if (n < 2) return n;\n  return fib(n - 1) + fib(n - 2);\n}\n\
+            var first;\nvar second;\nfor (var i = 0; i < 2; i = i + 1) {\n  var j = i;\n  \
+            fun show() { return j; }\n  if (i == 0) first = show; else second = show;\n}\n\
+            var result = list(fib(20), first(), second());";
+        assert_eq!(global(source, "result"), "[6765, 0, 1]");
+    }
+
+    #[test]
+    fn test_locals_resolve_to_slots() {
+        let source = "var a = \"global\";\nvar got;\n{\n  fun show() { return a; }\n  \
+            var first = show();\n  var a = \"local\";\n  got = list(first, show(), a);\n}\n\
+            var inner;\nvar outer;\n{\n  var x = 1;\n  var y = 2;\n  {\n    var x = 10;\n    \
+            inner = x + y;\n  }\n  {\n    var z = 100;\n    outer = x + y + z;\n  }\n}\n\
+            fun later() { return value; }\nvar value = 5;\n\
+            var result = list(got, inner, outer, later());";
+        assert_eq!(
+            global(source, "result"),
+            "[[global, global, local], 12, 103, 5]"
+        );
+    }
+
+    #[test]
+    fn test_smallest_integer_literal() {
+        let source = "var kind;\nmatch (-9223372036854775808) {\n  \
+            -9223372036854775808 => kind = \"min\",\n  _ => kind = \"other\",\n}\n\
+            var result = list(-9223372036854775808, kind);";
+        assert_eq!(global(source, "result"), "[-9223372036854775808, min]");
+        assert!(matches!(
+            run("print 9223372036854775808;", Limits::default()),
+            Err(LangError::ParseError)
+        ));
+    }
+
+    #[test]
+    fn test_compound_assignment_evaluates_target_once() {
+        let source = "class Counter {\n  init() { this.n = 0; }\n}\nvar counter = Counter();\n\
+            var calls = 0;\nfun g() {\n  calls += 1;\n  return counter;\n}\n\
+            g().n += 5;\ng().n++;\n++g().n;\nvar result = list(calls, counter.n);";
+        assert_eq!(global(source, "result"), "[3, 7]");
+    }
+
+    #[test]
+    fn test_increment_results() {
+        let source = "var a = 1;\nvar old = a++;\nvar b = 1;\nvar new = ++b;\n\
+            var c = 5;\nvar down = c--;\nvar result = list(old, a, new, b, down, c);";
+        assert_eq!(global(source, "result"), "[1, 2, 2, 2, 5, 4]");
+        assert!(matches!(
+            run("print 3--1;", Limits::default()),
+            Err(LangError::ParseError)
+        ));
+    }
+
+    #[test]
+    fn test_optional_chain_short_circuits() {
+        let source = "class P {\n  init() { this.q = nil; }\n}\nvar calls = 0;\n\
+            fun count() {\n  calls += 1;\n  return 0;\n}\nvar a = nil;\nvar p = P();\n\
+            var result = list(a?.b.c, p.q?.r.s, a?.b(count()).c, a?.b[count()], calls);";
+        assert_eq!(global(source, "result"), "[nil, nil, nil, nil, 0]");
+        // Parentheses end the chain
+        assert!(run("var a = nil;\nprint (a?.b).c;", Limits::default()).is_err());
+        assert!(matches!(
+            run("var a = nil;\na?.b = 3;", Limits::default()),
+            Err(LangError::ParseError)
+        ));
+        assert!(matches!(
+            run("var a = nil;\na?.b.c += 3;", Limits::default()),
+            Err(LangError::ParseError)
+        ));
+    }
+
+    #[test]
+    fn test_patterns_with_local_classes() {
+        let source = "fun g() {\n  class L {\n    init(a) { this.a = a; }\n  }\n  \
+            match (L(1)) {\n    L(z) => return z,\n    _ => return 0,\n  }\n}\nvar first = g();\n\
+            var second;\n{\n  class A {\n    init(a) { this.a = a; }\n  }\n  {\n    \
+            class L {\n      init(a) { this.a = a; }\n    }\n    match (A(1)) {\n      \
+            L(z) => second = \"L\",\n      A(_) | L(_) => second = \"A\",\n      \
+            _ => second = \"neither\",\n    }\n  }\n}\nvar result = list(first, second);";
+        assert_eq!(global(source, "result"), "[1, A]");
+    }
+
+    #[test]
+    fn test_guard_ending_in_parentheses() {
+        let source = "fun size(n) {\n  var a = 1;\n  var b = 2;\n  match (n) {\n    \
+            x if (x > 10) => return \"big\",\n    \
+            x if x > (a + b) => return \"medium\",\n    \
+            x if apply((y) => y > 0, x) => return \"small\",\n    \
+            _ => return \"none\",\n  }\n}\n\
+            fun apply(f, x) { return f(x); }\n\
+            var result = list(size(11), size(4), size(1), size(0));";
+        assert_eq!(global(source, "result"), "[big, medium, small, none]");
+    }
+
+    #[test]
+    fn test_for_in_with_var() {
+        let source = "var total = 0;\nfor (var x in 1..4) total += x;\n\
+            var chars = \"\";\nfor (c in \"abc\") chars = c + chars;\n\
+            var result = list(total, chars);";
+        assert_eq!(global(source, "result"), "[6, cba]");
+    }
+
+    #[test]
+    fn test_generator_yields_and_resumes() {
+        let source = "fun count(n) {\n  var i = 0;\n  while (i < n) {\n    yield i;\n    i += 1;\n  }\n}\n\
+            var g = count(3);\nvar result = list(g.next(), g.next(), g.next(), g.next(), g.next());";
+        assert_eq!(global(source, "result"), "[0, 1, 2, nil, nil]");
+    }
+
+    #[test]
+    fn test_generator_loop_control() {
+        let source = "fun pairs() {\n  for (a in 0..4) {\n    if (a == 1) continue;\n    \
+            for (var b = 0; b < 10; b = b + 1) {\n      if (b == 2) break;\n      \
+            yield a * 10 + b;\n    }\n    if (a == 2) break;\n  }\n  yield 99;\n}\n\
+            var g = pairs();\nvar result = list(g.next(), g.next(), g.next(), \
+            g.next(), g.next(), g.next());";
+        assert_eq!(global(source, "result"), "[0, 1, 20, 21, 99, nil]");
+    }
+
+    #[test]
+    fn test_generator_has_next_peeks() {
+        let source = "fun two() {\n  yield 1;\n  yield 2;\n}\nvar g = two();\n\
+            var result = list(g.hasNext(), g.hasNext(), g.next(), g.next(), g.hasNext(), \
+            g.next(), g.hasNext());\nvar shown = two();";
+        assert_eq!(
+            global(source, "result"),
+            "[true, true, 1, 2, false, nil, false]"
+        );
+        assert_eq!(global(source, "shown"), "generator <two>");
+    }
+
+    #[test]
+    fn test_generator_loops_are_limited() {
+        let limits = Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        };
+        let source =
+            "fun gen() {\n  while (true) {\n    if (false) yield 1;\n  }\n}\ngen().next();";
+        assert!(matches!(
+            exceeded(run(source, limits)),
+            Some((Limit::Steps, _))
+        ));
     }
 }
@@ -1,20 +1,54 @@
-use crate::{
-    object::Object,
-    scanner::token::{Token, TokenType},
-};
+use crate::scanner::token::{Token, TokenType};
 
 #[derive(Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum LangError {
     ParseError,
     ResolveError,
-    RuntimeError { message: String, line: u32 },
-    Return(Object),
+    RuntimeError {
+        message: String,
+        line: u32,
+    },
+    /// The script ran into one of the interpreter's limits rather than failing by itself
+    LimitExceeded {
+        limit: Limit,
+        line: u32,
+    },
+    /// The host cancelled the script, see `cancel::CancelHandle`
+    Interrupted {
+        line: u32,
+    },
+}
+
+/// The limits a host can put on a script, see `interpreter::Limits`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Steps,
+    Timeout,
+    CallDepth,
+    Objects,
+}
+
+impl Limit {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Limit::Steps => "Step budget exceeded.",
+            Limit::Timeout => "Timed out.",
+            Limit::CallDepth => "Stack overflow.",
+            Limit::Objects => "Too many objects.",
+        }
+    }
 }
 
 pub fn error(line_num: u32, message: String) {
     report(line_num, String::from(""), message);
 }
 
+/// Report something suspicious that doesn't stop the script from running
+pub fn warning(line_num: u32, message: String) {
+    println!("[line: {}] Warning: {}", line_num, message)
+}
+
 fn report(line_num: u32, location: String, message: String) {
     println!("[line: {}] Error{}: {}", line_num, location, message)
 }
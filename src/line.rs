@@ -0,0 +1,47 @@
+use crate::expr::Expr;
+use crate::stmt::Stmt;
+
+/// The line a statement starts on, if it has a token to tell. Blocks have
+/// none, but the statements inside them do.
+pub fn stmt_line(stmt: &Stmt) -> Option<u32> {
+    match stmt {
+        Stmt::Block(_) => None,
+        Stmt::Break(stmt) => Some(stmt.keyword.line),
+        Stmt::Class(stmt) => Some(stmt.name.line),
+        Stmt::Continue(stmt) => Some(stmt.keyword.line),
+        Stmt::Expression(stmt) => expr_line(&stmt.expression),
+        Stmt::ForIn(stmt) => Some(stmt.keyword.line),
+        Stmt::Function(stmt) => Some(stmt.name.line),
+        Stmt::If(stmt) => expr_line(&stmt.condition),
+        Stmt::Match(stmt) => Some(stmt.keyword.line),
+        Stmt::MultiAssign(stmt) => Some(stmt.equals.line),
+        Stmt::Print(stmt) => Some(stmt.keyword.line),
+        Stmt::Return(stmt) => Some(stmt.keyword.line),
+        Stmt::Trait(stmt) => Some(stmt.name.line),
+        Stmt::Var(stmt) => Some(stmt.names[0].line),
+        Stmt::While(stmt) => Some(stmt.keyword.line),
+        Stmt::Yield(stmt) => Some(stmt.keyword.line),
+    }
+}
+
+fn expr_line(expr: &Expr) -> Option<u32> {
+    match expr {
+        Expr::Assign(expr) => Some(expr.name.line),
+        Expr::Binary(expr) => expr_line(&expr.left),
+        Expr::Call(expr) => expr_line(&expr.callee),
+        Expr::Conditional(expr) => expr_line(&expr.condition),
+        Expr::Get(expr) => expr_line(&expr.object),
+        Expr::Grouping(expr) => expr_line(&expr.expression),
+        Expr::Index(expr) => expr_line(&expr.object),
+        Expr::Lambda(expr) => Some(expr.function.name.line),
+        Expr::Literal(_) => None,
+        Expr::Logical(expr) => expr_line(&expr.left),
+        Expr::Range(expr) => expr_line(&expr.start),
+        Expr::Set(expr) => expr_line(&expr.object),
+        Expr::Super(expr) => Some(expr.keyword.line),
+        Expr::This(expr) => Some(expr.keyword.line),
+        Expr::Tuple(expr) => expr.elements.first().and_then(expr_line),
+        Expr::Unary(expr) => Some(expr.operator.line),
+        Expr::Variable(expr) => Some(expr.name.line),
+    }
+}
@@ -3,32 +3,70 @@ use std::fs;
 use std::io::{self, Write};
 use std::path::Path;
 use std::process::exit;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
-use interpreter::Interpreter;
+use debugger::Debugger;
+use interpreter::{Interpreter, Limits, STACK_PER_CALL};
 use lang_error::LangError;
+use profiler::Profiler;
 use resolver::Resolver;
 
+mod bench;
+mod cancel;
+mod debugger;
 mod environment;
 mod expr;
+mod gc;
 mod interpreter;
 mod lang_error;
+mod line;
 mod object;
 mod parser;
+mod pattern;
+mod profiler;
 mod resolver;
 mod scanner;
 mod stmt;
 
-fn run_file(path: &Path, interpreter: &mut Interpreter) {
+#[derive(Default)]
+struct Options {
+    bench: bool,
+    debug: bool,
+    save_baseline: bool,
+    gc_stats: bool,
+    limits: Limits,
+    profile: bool,
+    profile_folded: Option<String>,
+}
+
+const USAGE: &str = "Usage: rlox [--gc-stress] [--gc-stats] [--max-call-depth=<n>] [--max-steps=<n>]\n            [--timeout=<ms>] [--max-objects=<n>] [--profile] [--profile-folded=<file>] [script]\n       rlox --debug script\n       rlox --bench [--save-baseline] [directory]";
+
+/// Every Lox call nests several visitor calls, so give the interpreter room
+/// for `DEFAULT_MAX_CALL_DEPTH` calls even in debug builds
+const STACK_SIZE: usize = 1024 * 1024 * 1024;
+
+/// The deepest `--max-call-depth` that `STACK_SIZE` can hold
+const MAX_CALL_DEPTH: usize = STACK_SIZE / STACK_PER_CALL;
+
+fn run_file(path: &Path, interpreter: &mut Interpreter) -> i32 {
     let source = fs::read_to_string(path).unwrap();
-    if let Err(e) = run(source, interpreter) {
-        match e {
-            LangError::RuntimeError { .. } => exit(70),
-            _ => exit(65),
-        }
-    };
+    match run(source, interpreter) {
+        Ok(()) => 0,
+        Err(
+            LangError::RuntimeError { .. }
+            | LangError::LimitExceeded { .. }
+            | LangError::Interrupted { .. },
+        ) => 70,
+        Err(_) => 65,
+    }
 }
 
-fn run_prompt(interpreter: &mut Interpreter) {
+fn run_prompt(interpreter: &mut Interpreter) -> i32 {
+    // Ctrl-C stops the line being evaluated rather than the whole REPL
+    let cancel = interpreter.cancel_handle();
+    cancel::cancel_on_sigint(&cancel);
     let stdin = io::stdin();
     loop {
         print!("> ");
@@ -36,14 +74,14 @@ fn run_prompt(interpreter: &mut Interpreter) {
         io::stdout().flush().unwrap();
         let bytes = stdin.read_line(&mut buffer).unwrap();
         if bytes == 0 {
-            exit(0);
+            return 0;
         }
         if buffer == "exit\n" {
-            exit(0)
+            return 0;
         }
-        match run(buffer.trim().to_string(), interpreter) {
-            _ => continue,
-        };
+        // Ctrl-C pressed at the prompt shouldn't stop the next line
+        cancel.clear();
+        let _ = run(buffer.trim().to_string(), interpreter);
     }
 }
 
@@ -52,27 +90,181 @@ fn run(source: String, interpreter: &mut Interpreter) -> Result<(), LangError> {
     let tokens = scanner.scan_tokens();
     let mut parser = parser::Parser::new(tokens);
     let statements = parser.parse()?;
-    let mut resolver = Resolver::new(interpreter.clone());
-    resolver.resolve_statements(statements.clone())?;
-    if let Err(ref e) = resolver.interpreter.interpret(statements) {
-        if let LangError::RuntimeError { message, line } = e {
-            lang_error::error(*line, message.to_string())
+    Resolver::new(interpreter).resolve_statements(&statements)?;
+    if let Err(ref e) = interpreter.interpret(statements) {
+        match e {
+            LangError::RuntimeError { message, line } => {
+                lang_error::error(*line, message.to_string())
+            }
+            LangError::LimitExceeded { limit, line } => {
+                lang_error::error(*line, limit.message().to_string())
+            }
+            LangError::Interrupted { line } => lang_error::error(*line, "Interrupted.".to_string()),
+            _ => (),
         }
         return Err(e.clone());
     }
     Ok(())
 }
 
+fn report_gc_stats() {
+    let stats = gc::stats();
+    eprintln!(
+        "[gc] collections: {}, allocated: {}, freed: {}, live: {}",
+        stats.collections, stats.allocated, stats.freed, stats.live
+    );
+}
+
+/// The value of a `--name=<value>` flag, exiting with the usage if it's malformed
+fn flag_value<T: FromStr>(flag: &str) -> T {
+    let value = flag.split_once('=').map_or("", |(_, value)| value);
+    value.parse().unwrap_or_else(|_| {
+        println!("{}", USAGE);
+        exit(64)
+    })
+}
+
+/// Check a `--max-call-depth` against the stack the interpreter thread has,
+/// since going past it would crash instead of reporting a Lox error
+fn max_call_depth(depth: usize) -> Result<usize, String> {
+    if depth > MAX_CALL_DEPTH {
+        return Err(format!(
+            "--max-call-depth can be at most {}.",
+            MAX_CALL_DEPTH
+        ));
+    }
+    Ok(depth)
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let mut interpreter = Interpreter::new();
-    if args.len() > 2 {
-        println!("Usage: rlox [script]");
+    let interpreter_thread = match thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_main)
+    {
+        Ok(thread) => thread,
+        Err(error) => {
+            eprintln!("Can't start the interpreter thread: {}", error);
+            exit(71)
+        }
+    };
+    exit(interpreter_thread.join().unwrap_or(70))
+}
+
+fn run_main() -> i32 {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (flags, paths): (Vec<&String>, Vec<&String>) =
+        args.iter().partition(|arg| arg.starts_with("--"));
+    let mut options = Options::default();
+    for flag in flags {
+        match flag.as_str() {
+            "--bench" => options.bench = true,
+            "--debug" => options.debug = true,
+            "--save-baseline" => options.save_baseline = true,
+            "--gc-stress" => gc::set_stress(true),
+            "--gc-stats" => options.gc_stats = true,
+            "--profile" => options.profile = true,
+            flag if flag.starts_with("--profile-folded=") => {
+                options.profile = true;
+                options.profile_folded = Some(flag["--profile-folded=".len()..].to_string());
+            }
+            flag if flag.starts_with("--max-call-depth=") => {
+                options.limits.max_call_depth =
+                    max_call_depth(flag_value(flag)).unwrap_or_else(|message| {
+                        eprintln!("{}", message);
+                        exit(64)
+                    })
+            }
+            flag if flag.starts_with("--max-steps=") => {
+                options.limits.max_steps = Some(flag_value(flag))
+            }
+            flag if flag.starts_with("--timeout=") => {
+                options.limits.timeout = Some(Duration::from_millis(flag_value(flag)))
+            }
+            flag if flag.starts_with("--max-objects=") => {
+                options.limits.max_objects = Some(flag_value(flag))
+            }
+            _ => {
+                println!("{}", USAGE);
+                exit(64)
+            }
+        }
+    }
+    let debug_without_script = options.debug && paths.is_empty();
+    if paths.len() > 1 || options.save_baseline && !options.bench || debug_without_script {
+        println!("{}", USAGE);
         exit(64)
-    } else if args.len() == 2 {
-        let path = Path::new(&args[1]);
-        run_file(path, &mut interpreter)
+    }
+    if options.bench {
+        let dir = paths
+            .first()
+            .map_or(bench::DEFAULT_DIR, |path| path.as_str());
+        return bench::run(Path::new(dir), options.save_baseline);
+    }
+    let mut interpreter = Interpreter::new();
+    interpreter.limits = options.limits;
+    if options.profile {
+        interpreter.profiler = Some(Profiler::new());
+    }
+    if options.debug {
+        let source = fs::read_to_string(paths[0]).unwrap();
+        environment::set_record_names(true);
+        interpreter.debugger = Some(Debugger::new(&source));
+    }
+    let code = if let Some(path) = paths.first() {
+        run_file(Path::new(path), &mut interpreter)
     } else {
         run_prompt(&mut interpreter)
+    };
+    if let Some(mut profiler) = interpreter.profiler.take() {
+        profiler.finish();
+        let source = paths.first().and_then(|path| fs::read_to_string(path).ok());
+        profiler.report(source.as_deref());
+        if let Some(path) = options.profile_folded {
+            if let Err(error) = profiler.write_folded(Path::new(&path)) {
+                eprintln!("Could not write {}: {}", path, error);
+            }
+        }
+    }
+    if options.gc_stats {
+        gc::collect();
+        report_gc_stats();
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_call_depth_is_capped() {
+        assert_eq!(
+            max_call_depth(interpreter::DEFAULT_MAX_CALL_DEPTH).ok(),
+            Some(interpreter::DEFAULT_MAX_CALL_DEPTH)
+        );
+        assert_eq!(max_call_depth(MAX_CALL_DEPTH).ok(), Some(MAX_CALL_DEPTH));
+        assert!(max_call_depth(MAX_CALL_DEPTH + 1).is_err());
+    }
+
+    #[test]
+    fn test_max_call_depth_fits_the_stack() {
+        // Each call goes through a method, a loop and a match to use more
+        // stack than a plain recursive function
+        let source = format!(
+            "class A {{\n  f(n) {{\n    while (n > 0) {{\n      match (n) {{\n        \
+             _ => {{ var r = 0 * this.f(n - 1); return r; }}\n      }}\n    }}\n    \
+             return 0;\n  }}\n}}\nA().f({});",
+            MAX_CALL_DEPTH - 1
+        );
+        let result = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let mut interpreter = Interpreter::new();
+                interpreter.limits.max_call_depth = MAX_CALL_DEPTH;
+                run(source, &mut interpreter).is_ok()
+            })
+            .unwrap()
+            .join();
+        assert_eq!(result.ok(), Some(true));
     }
 }
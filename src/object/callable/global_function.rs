@@ -1,9 +1,10 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::{
+    gc::{Trace, Tracer},
     interpreter::Interpreter,
     lang_error::LangError,
-    object::{literal_type::LiteralType, LoxCallable, Object},
+    object::{literal_type::LiteralType, Arguments, Arity, LoxCallable, Object},
 };
 
 #[derive(Clone, Debug)]
@@ -16,15 +17,16 @@ impl Clock {
 }
 
 impl LoxCallable for Clock {
-    fn arity(&self) -> usize {
-        0
+    fn arity(&self) -> Arity {
+        Arity::exact(0)
     }
 
     fn call(
         &self,
         _interpreter: &mut Interpreter,
-        _arguments: Vec<LiteralType>,
+        arguments: Arguments,
     ) -> Result<Object, LangError> {
+        arguments.into_positional()?;
         let current_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
         let ret = Object::Value(LiteralType::Num(current_time.as_secs_f64()));
         Ok(ret)
@@ -33,4 +35,53 @@ impl LoxCallable for Clock {
     fn to_string(&self) -> String {
         "native fn <Clock>".to_string()
     }
+
+    fn name(&self) -> String {
+        "clock".to_string()
+    }
+}
+
+impl Trace for Clock {
+    fn trace(&self, _tracer: &mut Tracer) {}
+}
+
+/// `freeze(value)` stops any more fields being set on an instance and returns
+/// it. Other values are returned unchanged.
+#[derive(Clone, Debug)]
+pub struct Freeze {}
+
+impl Freeze {
+    pub fn new() -> Freeze {
+        Freeze {}
+    }
+}
+
+impl LoxCallable for Freeze {
+    fn arity(&self) -> Arity {
+        Arity::exact(1)
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Arguments,
+    ) -> Result<Object, LangError> {
+        let value = arguments.into_positional()?.remove(0);
+        if let Object::Instance(instance) = &value {
+            instance.freeze();
+        }
+        Ok(value)
+    }
+
+    fn to_string(&self) -> String {
+        "native fn <Freeze>".to_string()
+    }
+
+    fn name(&self) -> String {
+        "freeze".to_string()
+    }
+}
+
+impl Trace for Freeze {
+    fn trace(&self, _tracer: &mut Tracer) {}
 }
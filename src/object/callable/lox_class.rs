@@ -1,73 +1,182 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
+    gc::{self, Trace, Tracer},
     interpreter::Interpreter,
     lang_error::LangError,
-    object::{literal_type::LiteralType, lox_instance::LoxInstance, LoxCallable, Object},
+    object::{lox_instance::LoxInstance, Arguments, Arity, LoxCallable, Object},
+    scanner::token::Token,
 };
 
 use super::lox_function::LoxFunction;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct LoxClass {
     pub name: String,
-    pub superclass: Option<Box<LoxClass>>,
+    pub superclass: Option<Rc<LoxClass>>,
     pub methods: HashMap<String, LoxFunction>,
+    pub class_methods: HashMap<String, LoxFunction>,
+    pub getters: HashMap<String, LoxFunction>,
+    fields: RefCell<HashMap<String, Object>>,
 }
 
 impl LoxClass {
     pub fn new(
         name: String,
-        superclass: Option<Box<LoxClass>>,
+        superclass: Option<Rc<LoxClass>>,
         methods: HashMap<String, LoxFunction>,
-    ) -> Object {
-        let lox_class = LoxClass {
+        class_methods: HashMap<String, LoxFunction>,
+        getters: HashMap<String, LoxFunction>,
+    ) -> Rc<LoxClass> {
+        let class = Rc::new(LoxClass {
             name,
             superclass,
             methods,
-        };
-        Object::Class(lox_class)
+            class_methods,
+            getters,
+            fields: RefCell::new(HashMap::new()),
+        });
+        gc::track_class(&class);
+        class
     }
 
-    pub fn find_method(&self, name: String) -> Option<LoxFunction> {
-        if let Some(method) = self.methods.get(&name) {
+    pub fn find_method(&self, name: &str) -> Option<LoxFunction> {
+        if let Some(method) = self.methods.get(name) {
             return Some(method.clone());
         }
 
-        if let Some(superclass) = self.superclass.clone() {
+        if let Some(superclass) = &self.superclass {
             superclass.find_method(name)
         } else {
             None
         }
     }
+
+    pub fn find_class_method(&self, name: &str) -> Option<LoxFunction> {
+        if let Some(method) = self.class_methods.get(name) {
+            return Some(method.clone());
+        }
+
+        if let Some(superclass) = &self.superclass {
+            superclass.find_class_method(name)
+        } else {
+            None
+        }
+    }
+
+    pub fn find_getter(&self, name: &str) -> Option<LoxFunction> {
+        if let Some(getter) = self.getters.get(name) {
+            return Some(getter.clone());
+        }
+
+        if let Some(superclass) = &self.superclass {
+            superclass.find_getter(name)
+        } else {
+            None
+        }
+    }
+
+    fn find_field(&self, name: &str) -> Option<Object> {
+        if let Some(value) = self.fields.borrow().get(name) {
+            return Some(value.clone());
+        }
+
+        if let Some(superclass) = &self.superclass {
+            superclass.find_field(name)
+        } else {
+            None
+        }
+    }
+
+    /// Look up a class-level field or a class method bound to this class
+    pub fn get(self: &Rc<Self>, name: &Token) -> Result<Object, LangError> {
+        if let Some(value) = self.find_field(&name.lexeme) {
+            return Ok(value);
+        }
+        if let Some(method) = self.find_class_method(&name.lexeme) {
+            return Ok(Object::function(method.bind(Object::Class(self.clone()))));
+        }
+        let message = format!("Undefined property '{}'.", name.lexeme);
+        Err(LangError::RuntimeError {
+            message,
+            line: name.line,
+        })
+    }
+
+    pub fn set(&self, name: &Token, value: Object) {
+        self.fields.borrow_mut().insert(name.lexeme.clone(), value);
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut fields) = self.fields.try_borrow_mut() {
+            fields.clear();
+        }
+    }
+
+    /// Whether `self` is `class` or inherits from it
+    pub fn is_subclass_of(&self, class: &Rc<LoxClass>) -> bool {
+        if std::ptr::eq(self, Rc::as_ptr(class)) {
+            return true;
+        }
+        match &self.superclass {
+            Some(superclass) => superclass.is_subclass_of(class),
+            None => false,
+        }
+    }
 }
 
-impl LoxCallable for LoxClass {
+impl LoxCallable for Rc<LoxClass> {
     fn call(
         &self,
         interpreter: &mut Interpreter,
-        arguments: Vec<LiteralType>,
+        arguments: Arguments,
     ) -> Result<Object, LangError> {
-        let instance = Rc::new(LoxInstance::new(self.clone()));
-        let initializer = self.methods.get("init");
+        let instance = LoxInstance::new(self.clone());
+        let initializer = self.find_method("init");
         if let Some(init_method) = initializer {
             init_method
-                .clone()
-                .bind(instance.clone())
+                .bind(Object::Instance(instance.clone()))
                 .call(interpreter, arguments)?;
         }
         Ok(Object::Instance(instance))
     }
 
-    fn arity(&self) -> usize {
-        let initializer = self.methods.get("init");
+    fn arity(&self) -> Arity {
+        let initializer = self.find_method("init");
         if let Some(init_method) = initializer {
             return init_method.arity();
         }
-        0
+        Arity::exact(0)
     }
 
     fn to_string(&self) -> String {
         self.name.clone()
     }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl Trace for LoxClass {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Some(superclass) = &self.superclass {
+            tracer.class(superclass);
+        }
+        self.methods
+            .values()
+            .chain(self.class_methods.values())
+            .chain(self.getters.values())
+            .for_each(|method| method.trace(tracer));
+        match self.fields.try_borrow() {
+            Ok(fields) => fields.values().for_each(|value| value.trace(tracer)),
+            Err(_) => tracer.pin(),
+        }
+    }
+}
+
+impl Trace for Rc<LoxClass> {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.class(self);
+    }
 }
@@ -1,23 +1,30 @@
-use std::rc::Rc;
+use std::{borrow::Cow, rc::Rc};
 
 use crate::{
     environment::Environment,
-    interpreter::Interpreter,
+    gc::{Trace, Tracer},
+    interpreter::{ExecResult, Interpreter, TailCall},
     lang_error::LangError,
-    object::{literal_type::LiteralType, lox_instance::LoxInstance, LoxCallable, Object},
+    object::{
+        literal_type::LiteralType, lox_generator::LoxGenerator, lox_list::LoxList, Arguments,
+        Arity, LoxCallable, Object,
+    },
+    scanner::token::Token,
     stmt::Function,
 };
 
 #[derive(Clone, Debug)]
 pub struct LoxFunction {
-    declaration: Function,
+    declaration: Rc<Function>,
     closure: Rc<Environment>,
     is_initializer: bool,
+    /// Made by `bind`, so the closure holds only `this`
+    is_bound: bool,
 }
 
 impl LoxFunction {
     pub fn new(
-        declaration: Function,
+        declaration: Rc<Function>,
         closure: Rc<Environment>,
         is_initializer: bool,
     ) -> LoxFunction {
@@ -25,63 +32,181 @@ impl LoxFunction {
             declaration,
             closure,
             is_initializer,
+            is_bound: false,
         }
     }
 
-    pub fn bind(self, instance: Rc<LoxInstance>) -> Self {
+    /// The names of the declared parameters, without the rest parameter
+    pub fn params(&self) -> &[Token] {
+        &self.declaration.params
+    }
+
+    /// Bind `this` to an instance, or to the class itself for class methods
+    pub fn bind(self, this: Object) -> Self {
         let environment = Environment::new(Some(self.closure));
-        let instance = Object::Instance(instance);
-        environment.define("this".to_string(), instance);
-        Self::new(self.declaration, environment, self.is_initializer)
+        environment.define("this", this);
+        LoxFunction {
+            is_bound: true,
+            ..Self::new(self.declaration, environment, self.is_initializer)
+        }
+    }
+
+    /// Whether both are the same function, which for bound methods means the
+    /// same method bound to the same receiver. Each property access binds the
+    /// method anew, so `a.m == a.m` relies on this.
+    pub fn is_same(&self, other: &LoxFunction) -> bool {
+        if !Rc::ptr_eq(&self.declaration, &other.declaration) {
+            return false;
+        }
+        if !(self.is_bound && other.is_bound) {
+            return Rc::ptr_eq(&self.closure, &other.closure);
+        }
+        let (this, other_this) = (self.closure.slots.borrow(), other.closure.slots.borrow());
+        let same_receiver = match (this.first(), other_this.first()) {
+            (Some(this), Some(other_this)) => this.equals(other_this),
+            _ => false,
+        };
+        same_receiver
+            && match (&self.closure.enclosing, &other.closure.enclosing) {
+                (Some(closure), Some(other_closure)) => Rc::ptr_eq(closure, other_closure),
+                _ => false,
+            }
+    }
+
+    /// Bind the arguments and run the body, leaving a tail call for `call` to
+    /// make. A generator's body only runs once the generator is asked for a value.
+    fn run(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Arguments,
+    ) -> Result<ExecResult, LangError> {
+        let new_environment = Environment::new(Some(self.closure.clone()));
+        let params = &self.declaration.params;
+        // A parameter can only be left out when named arguments skip over it
+        let line = match arguments.named.first() {
+            Some((name, _)) => name.line,
+            None => self.declaration.name.line,
+        };
+        let mut values: Vec<Option<Object>> = vec![None; params.len()];
+        let mut positional = arguments.positional.into_iter();
+        for (value, argument) in values.iter_mut().zip(&mut positional) {
+            *value = Some(argument);
+        }
+        for (name, argument) in arguments.named {
+            match params.iter().position(|param| param.lexeme == name.lexeme) {
+                Some(index) if values[index].is_none() => values[index] = Some(argument),
+                Some(_) => {
+                    let message =
+                        format!("Got more than one value for parameter '{}'.", name.lexeme);
+                    return Err(LangError::RuntimeError {
+                        message,
+                        line: name.line,
+                    });
+                }
+                None => {
+                    let message = format!("Unknown parameter '{}'.", name.lexeme);
+                    return Err(LangError::RuntimeError {
+                        message,
+                        line: name.line,
+                    });
+                }
+            }
+        }
+        let defaults = &self.declaration.defaults;
+        for ((param, default), value) in params.iter().zip(defaults).zip(values) {
+            let value = match (value, default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => {
+                    interpreter.evaluate_in(default, new_environment.clone())?
+                }
+                (None, None) => {
+                    let message = format!("Missing argument for parameter '{}'.", param.lexeme);
+                    return Err(LangError::RuntimeError { message, line });
+                }
+            };
+            new_environment.define(&param.lexeme, value);
+        }
+        if let Some(rest) = &self.declaration.rest {
+            let list = LoxList::new(positional.collect());
+            new_environment.define(&rest.lexeme, Object::List(list));
+        }
+
+        if self.declaration.generator {
+            let generator = LoxGenerator::new(self.declaration.clone(), new_environment);
+            return Ok(ExecResult::Return(Object::Generator(generator)));
+        }
+        interpreter.execute_block(&self.declaration.body, new_environment)
     }
 }
 
 impl LoxCallable for LoxFunction {
-    fn arity(&self) -> usize {
-        self.declaration.params.len()
+    fn arity(&self) -> Arity {
+        let params = self.declaration.params.len();
+        let defaults = self.declaration.defaults.iter().flatten().count();
+        Arity {
+            min: params - defaults,
+            max: self
+                .declaration
+                .rest
+                .as_ref()
+                .map_or(Some(params), |_| None),
+        }
     }
 
     fn call(
         &self,
         interpreter: &mut Interpreter,
-        arguments: Vec<LiteralType>,
+        arguments: Arguments,
     ) -> Result<Object, LangError> {
-        let new_environment = Environment::new(Some(self.closure.clone()));
-        for n in 0..self.declaration.params.len() {
-            new_environment.define(
-                self.declaration.params[n].clone().lexeme,
-                Object::Value(arguments[n].clone()),
-            );
-        }
-
-        if let Err(lang_error) = interpreter.execute_block(&self.declaration.body, new_environment)
-        {
-            match lang_error {
-                LangError::Return(ret) => {
-                    if self.is_initializer {
-                        return self.closure.get_at(
-                            0,
-                            "this".to_string(),
-                            self.declaration.name.line,
-                        );
+        let mut function = Cow::Borrowed(self);
+        let mut arguments = arguments;
+        let value = loop {
+            match function.run(interpreter, arguments)? {
+                ExecResult::TailCall(tail_call) => {
+                    match interpreter.tail_call_target(&tail_call)? {
+                        Some(target) => {
+                            interpreter.replace_frame(target.name());
+                            function = Cow::Owned(target);
+                            arguments = tail_call.arguments;
+                        }
+                        None => {
+                            let TailCall {
+                                callee,
+                                arguments,
+                                paren,
+                            } = *tail_call;
+                            break interpreter.call_object(callee, arguments, &paren)?;
+                        }
                     }
-                    return Ok(ret);
-                }
-                _ => {
-                    return Err(lang_error);
                 }
+                ExecResult::Return(value) => break value,
+                _ => break Object::Value(LiteralType::Nil),
             }
-        }
+        };
 
-        if self.is_initializer {
-            return self
+        if function.is_initializer {
+            return function
                 .closure
-                .get_at(0, "this".to_string(), self.declaration.name.line);
+                .get_at(0, 0, "this", function.declaration.name.line);
         }
-        Ok(Object::Value(LiteralType::Nil))
+        Ok(value)
+    }
+
+    fn as_lox_function(&self) -> Option<&LoxFunction> {
+        Some(self)
     }
 
     fn to_string(&self) -> String {
         format!("fn <{:?}>", self.declaration.name.lexeme)
     }
+
+    fn name(&self) -> String {
+        self.declaration.name.lexeme.clone()
+    }
+}
+
+impl Trace for LoxFunction {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.environment(&self.closure);
+    }
 }
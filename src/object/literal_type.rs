@@ -1,11 +1,13 @@
-use std::cmp;
+use std::cmp::{self, Ordering};
+use std::convert::TryFrom;
+use std::fmt;
 use std::ops;
-use substring::Substring;
 
 #[derive(Debug, Clone)]
 pub enum LiteralType {
     Str(String),
     Num(f64),
+    Int(i64),
     False,
     True,
     Nil,
@@ -21,85 +23,170 @@ pub fn convert_bool_to_literal_bool(b: bool) -> LiteralType {
 }
 
 pub fn is_truthy(literal_type: LiteralType) -> bool {
-    if let LiteralType::True = !!literal_type {
-        true
-    } else {
-        false
+    matches!(!!literal_type, LiteralType::True)
+}
+
+/// The operands of an arithmetic operator, promoted to floats unless both are integers
+enum Numbers {
+    Ints(i64, i64),
+    Floats(f64, f64),
+}
+
+fn numbers(left: &LiteralType, right: &LiteralType) -> Option<Numbers> {
+    let numbers = match (left, right) {
+        (LiteralType::Int(left), LiteralType::Int(right)) => Numbers::Ints(*left, *right),
+        (LiteralType::Int(left), LiteralType::Num(right)) => Numbers::Floats(*left as f64, *right),
+        (LiteralType::Num(left), LiteralType::Int(right)) => Numbers::Floats(*left, *right as f64),
+        (LiteralType::Num(left), LiteralType::Num(right)) => Numbers::Floats(*left, *right),
+        _ => return None,
+    };
+    Some(numbers)
+}
+
+/// How two numbers compare, or `None` when either is NaN. An integer and a
+/// float compare by their exact values, since converting a large integer to
+/// a float can round it.
+fn number_ordering(left: &LiteralType, right: &LiteralType) -> Option<Ordering> {
+    match (left, right) {
+        (LiteralType::Int(left), LiteralType::Int(right)) => Some(left.cmp(right)),
+        (LiteralType::Num(left), LiteralType::Num(right)) => left.partial_cmp(right),
+        (LiteralType::Int(left), LiteralType::Num(right)) => int_float_ordering(*left, *right),
+        (LiteralType::Num(left), LiteralType::Int(right)) => {
+            int_float_ordering(*right, *left).map(Ordering::reverse)
+        }
+        _ => None,
+    }
+}
+
+fn int_float_ordering(int: i64, float: f64) -> Option<Ordering> {
+    // 2^63 is the first float past i64::MAX, and -2^63 is i64::MIN
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if float.is_nan() {
+        return None;
     }
+    if float >= LIMIT {
+        return Some(Ordering::Less);
+    }
+    if float < -LIMIT {
+        return Some(Ordering::Greater);
+    }
+    // The whole part fits in an i64, leaving the fraction to break a tie
+    let whole = float.trunc();
+    Some(int.cmp(&(whole as i64)).then(whole.partial_cmp(&float)?))
+}
+
+fn checked(result: Option<i64>) -> LiteralType {
+    result.map_or_else(overflow, LiteralType::Int)
+}
+
+fn overflow() -> LiteralType {
+    LiteralType::Error("Integer overflow.".to_string())
 }
 
-impl LiteralType {
-    pub fn to_string(self) -> String {
+fn not_numbers() -> LiteralType {
+    LiteralType::Error("Operands must be numbers.".to_string())
+}
+
+impl fmt::Display for LiteralType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LiteralType::Nil => "nil".to_string(),
-            LiteralType::Num(n) => {
-                let num_in_str = n.to_string();
-                if num_in_str.ends_with(".0") {
-                    let str_len = num_in_str.len();
-                    return num_in_str.substring(0, str_len - 2).to_string();
-                }
-                num_in_str
-            }
-            LiteralType::Str(string) => string,
-            LiteralType::True => "true".to_string(),
-            LiteralType::False => "false".to_string(),
+            LiteralType::Nil => write!(f, "nil"),
+            // Debug is the shortest form that reads back as the same float,
+            // and keeps the `.0` that tells a float from an integer
+            LiteralType::Num(n) => write!(f, "{:?}", n),
+            LiteralType::Int(n) => write!(f, "{}", n),
+            LiteralType::Str(string) => write!(f, "{}", string),
+            LiteralType::True => write!(f, "true"),
+            LiteralType::False => write!(f, "false"),
             LiteralType::Error(_) => panic!("Handle Error before stringifying"),
         }
     }
 }
 
 pub mod comparison {
-    use super::LiteralType;
+    use super::{numbers, LiteralType, Ordering};
 
-    pub fn gt(left: LiteralType, right: LiteralType) -> LiteralType {
-        match (left, right) {
-            (LiteralType::Num(left_num), LiteralType::Num(right_num)) => {
-                if left_num > right_num {
-                    LiteralType::True
-                } else {
-                    LiteralType::False
-                }
-            }
-            _ => LiteralType::Error("Operands must be numbers.".to_string()),
+    fn compare(left: LiteralType, right: LiteralType, accept: fn(Ordering) -> bool) -> LiteralType {
+        if numbers(&left, &right).is_none() {
+            return super::not_numbers();
         }
+        // NaN is neither smaller, larger nor equal to anything
+        let ordering = super::number_ordering(&left, &right);
+        super::convert_bool_to_literal_bool(ordering.is_some_and(accept))
+    }
+
+    pub fn gt(left: LiteralType, right: LiteralType) -> LiteralType {
+        compare(left, right, Ordering::is_gt)
     }
 
     pub fn ge(left: LiteralType, right: LiteralType) -> LiteralType {
-        match (left, right) {
-            (LiteralType::Num(left_num), LiteralType::Num(right_num)) => {
-                if left_num >= right_num {
-                    LiteralType::True
-                } else {
-                    LiteralType::False
-                }
-            }
-            _ => LiteralType::Error("Operands must be numbers.".to_string()),
-        }
+        compare(left, right, Ordering::is_ge)
     }
 
     pub fn lt(left: LiteralType, right: LiteralType) -> LiteralType {
-        match (left, right) {
-            (LiteralType::Num(left_num), LiteralType::Num(right_num)) => {
-                if left_num < right_num {
-                    LiteralType::True
-                } else {
-                    LiteralType::False
-                }
-            }
-            _ => LiteralType::Error("Operands must be numbers.".to_string()),
-        }
+        compare(left, right, Ordering::is_lt)
     }
 
     pub fn le(left: LiteralType, right: LiteralType) -> LiteralType {
+        compare(left, right, Ordering::is_le)
+    }
+}
+
+/// Operators that only make sense on integers
+pub mod bitwise {
+    use super::LiteralType;
+
+    fn integers(left: LiteralType, right: LiteralType) -> Result<(i64, i64), LiteralType> {
         match (left, right) {
-            (LiteralType::Num(left_num), LiteralType::Num(right_num)) => {
-                if left_num <= right_num {
-                    LiteralType::True
-                } else {
-                    LiteralType::False
-                }
+            (LiteralType::Int(left), LiteralType::Int(right)) => Ok((left, right)),
+            _ => Err(LiteralType::Error("Operands must be integers.".to_string())),
+        }
+    }
+
+    fn shift_amount(amount: i64) -> Result<u32, LiteralType> {
+        if (0..i64::BITS as i64).contains(&amount) {
+            return Ok(amount as u32);
+        }
+        Err(LiteralType::Error(
+            "Shift amount must be between 0 and 63.".to_string(),
+        ))
+    }
+
+    pub fn and(left: LiteralType, right: LiteralType) -> LiteralType {
+        integers(left, right).map_or_else(|e| e, |(left, right)| LiteralType::Int(left & right))
+    }
+
+    pub fn or(left: LiteralType, right: LiteralType) -> LiteralType {
+        integers(left, right).map_or_else(|e| e, |(left, right)| LiteralType::Int(left | right))
+    }
+
+    pub fn xor(left: LiteralType, right: LiteralType) -> LiteralType {
+        integers(left, right).map_or_else(|e| e, |(left, right)| LiteralType::Int(left ^ right))
+    }
+
+    pub fn shl(left: LiteralType, right: LiteralType) -> LiteralType {
+        let shifted = integers(left, right).and_then(|(value, amount)| {
+            let amount = shift_amount(amount)?;
+            let shifted = value << amount;
+            // Bits shifted out, or into the sign bit, overflow
+            if shifted >> amount != value {
+                return Err(super::overflow());
             }
-            _ => LiteralType::Error("Operands must be numbers.".to_string()),
+            Ok(shifted)
+        });
+        shifted.map_or_else(|e| e, LiteralType::Int)
+    }
+
+    pub fn shr(left: LiteralType, right: LiteralType) -> LiteralType {
+        let shifted =
+            integers(left, right).and_then(|(value, amount)| Ok(value >> shift_amount(amount)?));
+        shifted.map_or_else(|e| e, LiteralType::Int)
+    }
+
+    pub fn not(right: LiteralType) -> LiteralType {
+        match right {
+            LiteralType::Int(n) => LiteralType::Int(!n),
+            _ => LiteralType::Error("Operand must be an integer.".to_string()),
         }
     }
 }
@@ -108,9 +195,10 @@ impl ops::Sub for LiteralType {
     type Output = Self;
 
     fn sub(self, right: Self) -> Self::Output {
-        match (self, right) {
-            (Self::Num(left_num), Self::Num(right_num)) => LiteralType::Num(left_num - right_num),
-            _ => LiteralType::Error("Operands must be numbers.".to_string()),
+        match numbers(&self, &right) {
+            Some(Numbers::Ints(left, right)) => checked(left.checked_sub(right)),
+            Some(Numbers::Floats(left, right)) => LiteralType::Num(left - right),
+            None => not_numbers(),
         }
     }
 }
@@ -119,23 +207,21 @@ impl ops::Add for LiteralType {
     type Output = Self;
 
     fn add(self, right: Self) -> Self::Output {
+        match numbers(&self, &right) {
+            Some(Numbers::Ints(left, right)) => return checked(left.checked_add(right)),
+            Some(Numbers::Floats(left, right)) => return LiteralType::Num(left + right),
+            None => (),
+        }
         match (self, right) {
-            (Self::Num(left_num), Self::Num(right_num)) => LiteralType::Num(left_num + right_num),
             (Self::Str(left_str), Self::Str(right_str)) => LiteralType::Str(left_str + &right_str),
-            (Self::Str(left_str), Self::Num(right_num)) => {
-                LiteralType::Str(left_str + &right_num.to_string())
-            }
-            (Self::Str(left_str), Self::Nil) => LiteralType::Str(left_str + "nil"),
-            (Self::Str(left_str), Self::True) => LiteralType::Str(left_str + "true"),
-            (Self::Str(left_str), Self::False) => LiteralType::Str(left_str + "false"),
-            (Self::Num(left_num), Self::Str(right_str)) => {
-                LiteralType::Str(left_num.to_string() + &right_str)
-            }
-            (Self::Nil, Self::Str(right_str)) => LiteralType::Str("nil".to_string() + &right_str),
-            (Self::True, Self::Str(right_str)) => LiteralType::Str("true".to_string() + &right_str),
-            (Self::False, Self::Str(right_str)) => {
-                LiteralType::Str("false".to_string() + &right_str)
-            }
+            (
+                Self::Str(left_str),
+                right @ (Self::Num(_) | Self::Int(_) | Self::Nil | Self::True | Self::False),
+            ) => LiteralType::Str(left_str + &right.to_string()),
+            (
+                left @ (Self::Num(_) | Self::Int(_) | Self::Nil | Self::True | Self::False),
+                Self::Str(right_str),
+            ) => LiteralType::Str(left.to_string() + &right_str),
             _ => LiteralType::Error("Operands must be numbers or strings.".to_string()),
         }
     }
@@ -145,21 +231,62 @@ impl ops::Mul for LiteralType {
     type Output = Self;
 
     fn mul(self, right: Self) -> Self::Output {
-        match (self, right) {
-            (Self::Num(left_num), Self::Num(right_num)) => LiteralType::Num(left_num * right_num),
-            _ => LiteralType::Error("Operands must be numbers.".to_string()),
+        match numbers(&self, &right) {
+            Some(Numbers::Ints(left, right)) => checked(left.checked_mul(right)),
+            Some(Numbers::Floats(left, right)) => LiteralType::Num(left * right),
+            None => not_numbers(),
         }
     }
 }
 
+/// Integers divide to an integer, rounding towards zero
 impl ops::Div for LiteralType {
     type Output = Self;
 
     fn div(self, right: Self) -> Self::Output {
-        match (self, right) {
-            (Self::Num(left_num), Self::Num(right_num)) => LiteralType::Num(left_num / right_num),
-            _ => LiteralType::Error("Operands must be numbers.".to_string()),
+        match numbers(&self, &right) {
+            Some(Numbers::Ints(_, 0)) => LiteralType::Error("Division by zero.".to_string()),
+            Some(Numbers::Ints(left, right)) => checked(left.checked_div(right)),
+            Some(Numbers::Floats(left, right)) => LiteralType::Num(left / right),
+            None => not_numbers(),
+        }
+    }
+}
+
+/// The remainder has the sign of the left operand
+impl ops::Rem for LiteralType {
+    type Output = Self;
+
+    fn rem(self, right: Self) -> Self::Output {
+        match numbers(&self, &right) {
+            Some(Numbers::Ints(_, 0)) => LiteralType::Error("Division by zero.".to_string()),
+            // Only i64::MIN % -1 overflows, and its remainder is 0
+            Some(Numbers::Ints(left, right)) => {
+                LiteralType::Int(left.checked_rem(right).unwrap_or(0))
+            }
+            Some(Numbers::Floats(left, right)) => LiteralType::Num(left % right),
+            None => not_numbers(),
+        }
+    }
+}
+
+/// `**`, which stays an integer unless the exponent is negative
+pub fn power(left: LiteralType, right: LiteralType) -> LiteralType {
+    match numbers(&left, &right) {
+        Some(Numbers::Ints(base, exponent)) if exponent < 0 => {
+            LiteralType::Num((base as f64).powf(exponent as f64))
         }
+        Some(Numbers::Ints(base, exponent)) => match u32::try_from(exponent) {
+            Ok(exponent) => checked(base.checked_pow(exponent)),
+            // Only these bases don't overflow with such a large exponent
+            Err(_) => match base {
+                0 | 1 => LiteralType::Int(base),
+                -1 => LiteralType::Int(if exponent % 2 == 0 { 1 } else { -1 }),
+                _ => overflow(),
+            },
+        },
+        Some(Numbers::Floats(base, exponent)) => LiteralType::Num(base.powf(exponent)),
+        None => not_numbers(),
     }
 }
 
@@ -167,10 +294,11 @@ impl ops::Neg for LiteralType {
     type Output = Self;
 
     fn neg(self) -> Self::Output {
-        if let LiteralType::Num(n) = self {
-            return LiteralType::Num(-n);
+        match self {
+            LiteralType::Int(n) => checked(n.checked_neg()),
+            LiteralType::Num(n) => LiteralType::Num(-n),
+            _ => LiteralType::Error("Operand must be a number.".to_string()),
         }
-        LiteralType::Error("Operand must be a number.".to_string())
     }
 }
 
@@ -185,21 +313,20 @@ impl ops::Not for LiteralType {
     }
 }
 
+/// Integers and floats are equal when they have the same value
 impl cmp::PartialEq for LiteralType {
     fn eq(&self, right: &Self) -> bool {
+        if numbers(self, right).is_some() {
+            return number_ordering(self, right) == Some(cmp::Ordering::Equal);
+        }
         match self {
-            Self::Num(left_num) => {
-                if let LiteralType::Num(right_num) = right.clone() {
-                    return *left_num == right_num;
-                };
-                false
-            }
             Self::Str(left_str) => {
                 if let LiteralType::Str(right_str) = right.clone() {
                     return *left_str == right_str;
                 };
                 false
             }
+            Self::Num(_) | Self::Int(_) => false,
             Self::Nil => matches!(right, Self::Nil),
             Self::True => matches!(right, Self::True),
             Self::False => matches!(right, Self::False),
@@ -212,6 +339,10 @@ impl cmp::PartialEq for LiteralType {
 mod tests {
     use super::*;
 
+    fn is_error(value: LiteralType, expected: &str) -> bool {
+        matches!(value, LiteralType::Error(message) if message == expected)
+    }
+
     mod ops {
         use super::*;
 
@@ -228,8 +359,11 @@ mod tests {
             let right = LiteralType::Str(" World".to_string());
             assert_eq!(left + right, LiteralType::Str("Hello World".to_string()));
             let left = LiteralType::Str("Hello".to_string());
-            let right = LiteralType::Num(123.0);
+            let right = LiteralType::Int(123);
             assert_eq!(left + right, LiteralType::Str("Hello123".to_string()));
+            let left = LiteralType::Str("Hello".to_string());
+            let right = LiteralType::Num(1.5);
+            assert_eq!(left + right, LiteralType::Str("Hello1.5".to_string()));
             let left = LiteralType::Nil;
             let right = LiteralType::Str(" World".to_string());
             assert_eq!(left + right, LiteralType::Str("nil World".to_string()));
@@ -256,6 +390,111 @@ mod tests {
             assert_eq!(left / right, LiteralType::Num(3.0))
         }
 
+        #[test]
+        fn test_int_ops() {
+            assert!(matches!(
+                LiteralType::Int(2) + LiteralType::Int(1),
+                LiteralType::Int(3)
+            ));
+            assert!(matches!(
+                LiteralType::Int(7) / LiteralType::Int(2),
+                LiteralType::Int(3)
+            ));
+            assert!(matches!(
+                LiteralType::Int(-7) % LiteralType::Int(2),
+                LiteralType::Int(-1)
+            ));
+        }
+
+        #[test]
+        fn test_mixed_ops_promote_to_float() {
+            assert!(matches!(
+                LiteralType::Int(7) / LiteralType::Num(2.0),
+                LiteralType::Num(n) if n == 3.5
+            ));
+            assert!(matches!(
+                LiteralType::Num(0.5) * LiteralType::Int(3),
+                LiteralType::Num(n) if n == 1.5
+            ));
+        }
+
+        #[test]
+        fn test_int_errors() {
+            let overflow = "Integer overflow.";
+            let zero = "Division by zero.";
+            assert!(is_error(
+                LiteralType::Int(i64::MAX) + LiteralType::Int(1),
+                overflow
+            ));
+            assert!(is_error(
+                LiteralType::Int(i64::MIN) / LiteralType::Int(-1),
+                overflow
+            ));
+            assert!(is_error(-LiteralType::Int(i64::MIN), overflow));
+            assert!(is_error(LiteralType::Int(1) / LiteralType::Int(0), zero));
+            assert!(is_error(LiteralType::Int(1) % LiteralType::Int(0), zero));
+            assert!(matches!(
+                LiteralType::Int(i64::MIN) % LiteralType::Int(-1),
+                LiteralType::Int(0)
+            ));
+        }
+
+        #[test]
+        fn test_bitwise() {
+            let left = LiteralType::Int(0b1100);
+            let right = LiteralType::Int(0b1010);
+            assert_eq!(
+                bitwise::and(left.clone(), right.clone()),
+                LiteralType::Int(0b1000)
+            );
+            assert_eq!(
+                bitwise::or(left.clone(), right.clone()),
+                LiteralType::Int(0b1110)
+            );
+            assert_eq!(bitwise::xor(left, right), LiteralType::Int(0b0110));
+            assert_eq!(bitwise::not(LiteralType::Int(0)), LiteralType::Int(-1));
+            assert_eq!(
+                bitwise::shl(LiteralType::Int(1), LiteralType::Int(4)),
+                LiteralType::Int(16)
+            );
+            assert_eq!(
+                bitwise::shr(LiteralType::Int(-16), LiteralType::Int(2)),
+                LiteralType::Int(-4)
+            );
+            assert!(is_error(
+                bitwise::shl(LiteralType::Int(1), LiteralType::Int(63)),
+                "Integer overflow."
+            ));
+            assert!(is_error(
+                bitwise::and(LiteralType::Num(1.0), LiteralType::Int(1)),
+                "Operands must be integers."
+            ));
+        }
+
+        #[test]
+        fn test_power() {
+            assert!(matches!(
+                power(LiteralType::Int(2), LiteralType::Int(10)),
+                LiteralType::Int(1024)
+            ));
+            assert!(matches!(
+                power(LiteralType::Int(2), LiteralType::Int(-1)),
+                LiteralType::Num(n) if n == 0.5
+            ));
+            assert!(matches!(
+                power(LiteralType::Num(4.0), LiteralType::Num(0.5)),
+                LiteralType::Num(n) if n == 2.0
+            ));
+            assert!(matches!(
+                power(LiteralType::Int(-1), LiteralType::Int(i64::MAX)),
+                LiteralType::Int(-1)
+            ));
+            assert!(is_error(
+                power(LiteralType::Int(2), LiteralType::Int(64)),
+                "Integer overflow."
+            ));
+        }
+
         #[test]
         fn test_neg() {
             let right = LiteralType::Num(3.0);
@@ -287,6 +526,32 @@ mod tests {
             assert_ne!(left, right)
         }
 
+        #[test]
+        fn test_eq_int_num() {
+            assert_eq!(LiteralType::Int(1), LiteralType::Num(1.0));
+            assert_ne!(LiteralType::Int(1), LiteralType::Num(1.5));
+        }
+
+        #[test]
+        fn test_eq_int_num_is_exact() {
+            // 2^53 + 1 rounds to 2^53 as a float
+            let big = LiteralType::Int(9007199254740993);
+            assert_ne!(big, LiteralType::Num(9007199254740992.0));
+            assert_eq!(
+                LiteralType::Num(9007199254740992.0),
+                LiteralType::Int(1 << 53)
+            );
+            assert_ne!(
+                LiteralType::Int(i64::MAX),
+                LiteralType::Num(i64::MAX as f64)
+            );
+            assert_eq!(
+                LiteralType::Int(i64::MIN),
+                LiteralType::Num(i64::MIN as f64)
+            );
+            assert_ne!(LiteralType::Int(0), LiteralType::Num(f64::NAN));
+        }
+
         #[test]
         fn test_eq_others() {
             let nil = LiteralType::Nil;
@@ -300,6 +565,20 @@ mod tests {
         }
     }
 
+    mod display {
+        use super::*;
+
+        #[test]
+        fn test_display_exact() {
+            assert_eq!(
+                LiteralType::Int(i64::MAX).to_string(),
+                "9223372036854775807"
+            );
+            assert_eq!(LiteralType::Num(3.0).to_string(), "3.0");
+            assert_eq!(LiteralType::Num(0.1).to_string(), "0.1");
+        }
+    }
+
     mod cmp {
         use super::*;
 
@@ -334,5 +613,37 @@ mod tests {
             right = LiteralType::Num(1.0);
             assert_eq!(comparison::le(left, right), LiteralType::True)
         }
+
+        #[test]
+        fn test_cmp_int_num_is_exact() {
+            let big = LiteralType::Int(9007199254740993);
+            let float = LiteralType::Num(9007199254740992.0);
+            assert_eq!(
+                comparison::gt(big.clone(), float.clone()),
+                LiteralType::True
+            );
+            assert_eq!(comparison::lt(float, big), LiteralType::True);
+            let max = LiteralType::Int(i64::MAX);
+            assert_eq!(
+                comparison::lt(max.clone(), LiteralType::Num(i64::MAX as f64)),
+                LiteralType::True
+            );
+            assert_eq!(
+                comparison::gt(max, LiteralType::Num(f64::NEG_INFINITY)),
+                LiteralType::True
+            );
+            assert_eq!(
+                comparison::lt(LiteralType::Int(-2), LiteralType::Num(-1.5)),
+                LiteralType::True
+            );
+            assert_eq!(
+                comparison::gt(LiteralType::Int(-1), LiteralType::Num(-1.5)),
+                LiteralType::True
+            );
+            assert_eq!(
+                comparison::ge(LiteralType::Int(1), LiteralType::Num(f64::NAN)),
+                LiteralType::False
+            );
+        }
     }
 }
@@ -0,0 +1,383 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    slice,
+};
+
+use crate::{
+    environment::Environment,
+    gc::{self, Trace, Tracer},
+    interpreter::{ExecResult, Interpreter, Iteration},
+    lang_error::LangError,
+    object::{
+        literal_type::{self, LiteralType},
+        Arguments, Arity, LoxCallable, Object,
+    },
+    stmt::{Function, Stmt},
+};
+
+/// A call to a function containing `yield`, which runs its body a piece at a
+/// time. The interpreter's Rust stack can't be kept between the pieces, so
+/// the generator keeps its own stack of the statements it is part way through.
+/// Statements without a `yield` inside still run through the interpreter.
+#[derive(Debug)]
+pub struct LoxGenerator {
+    declaration: Rc<Function>,
+    /// Empty once the body has finished
+    frames: RefCell<Vec<Frame>>,
+    /// A value `hasNext` ran ahead to, waiting for `next`
+    peeked: RefCell<Option<Object>>,
+    running: Cell<bool>,
+}
+
+/// Statements being run in order, and what they belong to
+#[derive(Debug)]
+struct Frame {
+    next: usize,
+    environment: Rc<Environment>,
+    kind: Kind,
+}
+
+/// Where the statements of a frame come from. The first frame runs the body
+/// of the function, and every other one runs part of the statement its parent
+/// frame is on.
+#[derive(Debug)]
+enum Kind {
+    Block,
+    /// The `then` or `else` statement of an `if`
+    Branch(bool),
+    While,
+    ForIn(Iteration),
+    /// The body of the match arm at this index
+    Arm(usize),
+}
+
+impl Frame {
+    fn new(environment: Rc<Environment>, kind: Kind) -> Frame {
+        Frame {
+            next: 0,
+            environment,
+            kind,
+        }
+    }
+}
+
+impl LoxGenerator {
+    pub fn new(declaration: Rc<Function>, environment: Rc<Environment>) -> Rc<LoxGenerator> {
+        let generator = Rc::new(LoxGenerator {
+            declaration,
+            frames: RefCell::new(vec![Frame::new(environment, Kind::Block)]),
+            peeked: RefCell::new(None),
+            running: Cell::new(false),
+        });
+        gc::track_generator(&generator);
+        generator
+    }
+
+    pub fn name(&self) -> &str {
+        &self.declaration.name.lexeme
+    }
+
+    /// The next value yielded, or `None` once the body has finished
+    pub fn advance(
+        &self,
+        interpreter: &mut Interpreter,
+        line: u32,
+    ) -> Result<Option<Object>, LangError> {
+        if let Some(value) = self.peeked.borrow_mut().take() {
+            return Ok(Some(value));
+        }
+        if self.running.get() {
+            return Err(LangError::RuntimeError {
+                message: "Generator is already running.".to_string(),
+                line,
+            });
+        }
+        let mut frames = std::mem::take(&mut *self.frames.borrow_mut());
+        if frames.is_empty() {
+            return Ok(None);
+        }
+        self.running.set(true);
+        let previous_environment = interpreter.environment.clone();
+        let result = run(interpreter, &self.declaration.body, &mut frames, 0);
+        interpreter.environment = previous_environment;
+        self.running.set(false);
+        // Anything but a yield finishes the generator, which drops its frames
+        match result? {
+            ExecResult::Yield(value) => {
+                *self.frames.borrow_mut() = frames;
+                Ok(Some(value))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn has_next(&self, interpreter: &mut Interpreter, line: u32) -> Result<bool, LangError> {
+        if self.peeked.borrow().is_some() {
+            return Ok(true);
+        }
+        let value = self.advance(interpreter, line)?;
+        let has_next = value.is_some();
+        *self.peeked.borrow_mut() = value;
+        Ok(has_next)
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut frames) = self.frames.try_borrow_mut() {
+            frames.clear();
+        }
+        if let Ok(mut peeked) = self.peeked.try_borrow_mut() {
+            peeked.take();
+        }
+    }
+}
+
+/// Run the statements of `frames[depth]` until one yields or they finish
+fn run(
+    interpreter: &mut Interpreter,
+    statements: &[Stmt],
+    frames: &mut Vec<Frame>,
+    depth: usize,
+) -> Result<ExecResult, LangError> {
+    loop {
+        if frames.len() > depth + 1 {
+            let statement = &statements[frames[depth].next - 1];
+            match run_child(interpreter, statement, frames, depth + 1)? {
+                ExecResult::Normal => (),
+                result => return Ok(result),
+            }
+        }
+        let frame = &mut frames[depth];
+        let statement = match statements.get(frame.next) {
+            Some(statement) => statement,
+            None => return Ok(ExecResult::Normal),
+        };
+        frame.next += 1;
+        interpreter.environment = frame.environment.clone();
+        if contains_yield(statement) && !matches!(statement, Stmt::Yield(_)) {
+            // What `execute` would do first, which a loop that never reaches
+            // a plain statement relies on to be stopped
+            interpreter.before_statement(statement)?;
+            interpreter.profile_hit(statement);
+            if let Some(child) = start(interpreter, statement, frame.environment.clone())? {
+                frames.push(child);
+            }
+        } else {
+            match interpreter.execute(statement)? {
+                ExecResult::Normal => (),
+                result => return Ok(result),
+            }
+        }
+    }
+}
+
+/// Run `frames[depth]`, which is part of `statement`, going round again while
+/// it is a loop. The frame is dropped once it finishes.
+fn run_child(
+    interpreter: &mut Interpreter,
+    statement: &Stmt,
+    frames: &mut Vec<Frame>,
+    depth: usize,
+) -> Result<ExecResult, LangError> {
+    let is_loop = matches!(frames[depth].kind, Kind::While | Kind::ForIn(_));
+    loop {
+        let statements = child_statements(statement, &frames[depth].kind);
+        let result = run(interpreter, statements, frames, depth)?;
+        if let ExecResult::Yield(_) = result {
+            return Ok(result);
+        }
+        let next_iteration = matches!(result, ExecResult::Normal | ExecResult::Continue);
+        if is_loop && next_iteration && next_loop_iteration(interpreter, statement, frames, depth)?
+        {
+            continue;
+        }
+        frames.truncate(depth);
+        return Ok(match result {
+            ExecResult::Break | ExecResult::Continue if is_loop => ExecResult::Normal,
+            result => result,
+        });
+    }
+}
+
+/// The frame for a statement with a `yield` inside, if it runs anything
+fn start(
+    interpreter: &mut Interpreter,
+    statement: &Stmt,
+    environment: Rc<Environment>,
+) -> Result<Option<Frame>, LangError> {
+    let frame = match statement {
+        Stmt::Block(_) => Some(Frame::new(Environment::new(Some(environment)), Kind::Block)),
+        Stmt::If(stmt) => {
+            let then_branch = interpreter.evaluate(&stmt.condition)?.is_truthy();
+            if then_branch || stmt.else_statement.is_some() {
+                Some(Frame::new(environment, Kind::Branch(then_branch)))
+            } else {
+                None
+            }
+        }
+        Stmt::While(stmt) => {
+            interpreter.check_cancelled(stmt.keyword.line)?;
+            if interpreter.evaluate(&stmt.condition)?.is_truthy() {
+                Some(Frame::new(environment, Kind::While))
+            } else {
+                None
+            }
+        }
+        Stmt::ForIn(stmt) => {
+            let mut iteration = interpreter.iteration(&stmt.iterable, &stmt.keyword)?;
+            interpreter.check_cancelled(stmt.keyword.line)?;
+            match interpreter.next_element(&mut iteration, &stmt.keyword)? {
+                Some(element) => {
+                    let environment = Environment::new(Some(environment));
+                    environment.define(&stmt.name.lexeme, element);
+                    Some(Frame::new(environment, Kind::ForIn(iteration)))
+                }
+                None => None,
+            }
+        }
+        Stmt::Match(stmt) => interpreter
+            .match_arm(stmt)?
+            .map(|(arm, environment)| Frame::new(environment, Kind::Arm(arm))),
+        _ => None,
+    };
+    Ok(frame)
+}
+
+/// Set up the loop in `frames[depth]` for its next iteration, or tell it to stop
+fn next_loop_iteration(
+    interpreter: &mut Interpreter,
+    statement: &Stmt,
+    frames: &mut [Frame],
+    depth: usize,
+) -> Result<bool, LangError> {
+    let environment = frames[depth - 1].environment.clone();
+    interpreter.environment = environment.clone();
+    let frame = &mut frames[depth];
+    match (statement, &mut frame.kind) {
+        (Stmt::While(stmt), _) => {
+            if let Some(increment) = &stmt.increment {
+                interpreter.evaluate(increment)?;
+            }
+            interpreter.check_cancelled(stmt.keyword.line)?;
+            if !interpreter.evaluate(&stmt.condition)?.is_truthy() {
+                return Ok(false);
+            }
+            frame.environment = environment;
+        }
+        (Stmt::ForIn(stmt), Kind::ForIn(iteration)) => {
+            interpreter.check_cancelled(stmt.keyword.line)?;
+            let element = match interpreter.next_element(iteration, &stmt.keyword)? {
+                Some(element) => element,
+                None => return Ok(false),
+            };
+            frame.environment = Environment::new(Some(environment));
+            frame.environment.define(&stmt.name.lexeme, element);
+        }
+        _ => return Ok(false),
+    }
+    frame.next = 0;
+    Ok(true)
+}
+
+/// The statements a frame of the given kind runs within `statement`
+fn child_statements<'a>(statement: &'a Stmt, kind: &Kind) -> &'a [Stmt] {
+    match (statement, kind) {
+        (Stmt::Block(block), _) => &block.statements,
+        (Stmt::If(stmt), Kind::Branch(true)) => slice::from_ref(&*stmt.then_statement),
+        (Stmt::If(stmt), _) => stmt.else_statement.as_deref().map_or(&[], slice::from_ref),
+        (Stmt::While(stmt), _) => slice::from_ref(&*stmt.body),
+        (Stmt::ForIn(stmt), _) => slice::from_ref(&*stmt.body),
+        (Stmt::Match(stmt), Kind::Arm(arm)) => slice::from_ref(&*stmt.arms[*arm].body),
+        _ => &[],
+    }
+}
+
+/// Whether running the statement can yield. Functions and classes declared
+/// inside don't count, since their bodies run when they are called.
+pub fn contains_yield(statement: &Stmt) -> bool {
+    match statement {
+        Stmt::Yield(_) => true,
+        Stmt::Block(block) => block.statements.iter().any(contains_yield),
+        Stmt::If(stmt) => {
+            contains_yield(&stmt.then_statement)
+                || stmt.else_statement.as_deref().is_some_and(contains_yield)
+        }
+        Stmt::While(stmt) => contains_yield(&stmt.body),
+        Stmt::ForIn(stmt) => contains_yield(&stmt.body),
+        Stmt::Match(stmt) => stmt.arms.iter().any(|arm| contains_yield(&arm.body)),
+        _ => false,
+    }
+}
+
+impl Trace for LoxGenerator {
+    fn trace(&self, tracer: &mut Tracer) {
+        match self.frames.try_borrow() {
+            Ok(frames) => {
+                for frame in frames.iter() {
+                    tracer.environment(&frame.environment);
+                    if let Kind::ForIn(iteration) = &frame.kind {
+                        iteration.trace(tracer);
+                    }
+                }
+            }
+            Err(_) => tracer.pin(),
+        }
+        match self.peeked.try_borrow() {
+            Ok(peeked) => peeked.iter().for_each(|value| value.trace(tracer)),
+            Err(_) => tracer.pin(),
+        }
+    }
+}
+
+/// `next` or `hasNext` of a generator, looked up like a bound method
+#[derive(Debug)]
+pub struct GeneratorMethod {
+    generator: Rc<LoxGenerator>,
+    name: String,
+}
+
+impl GeneratorMethod {
+    pub fn new(generator: Rc<LoxGenerator>, name: &str) -> GeneratorMethod {
+        GeneratorMethod {
+            generator,
+            name: name.to_string(),
+        }
+    }
+}
+
+impl LoxCallable for GeneratorMethod {
+    fn arity(&self) -> Arity {
+        Arity::exact(0)
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Arguments,
+    ) -> Result<Object, LangError> {
+        arguments.into_positional()?;
+        let line = self.generator.declaration.name.line;
+        if self.name == "hasNext" {
+            let has_next = self.generator.has_next(interpreter, line)?;
+            return Ok(Object::Value(literal_type::convert_bool_to_literal_bool(
+                has_next,
+            )));
+        }
+        let value = self.generator.advance(interpreter, line)?;
+        Ok(value.unwrap_or(Object::Value(LiteralType::Nil)))
+    }
+
+    fn to_string(&self) -> String {
+        format!("native fn <{}>", self.name)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl Trace for GeneratorMethod {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.generator(&self.generator);
+    }
+}
@@ -1,31 +1,47 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    rc::Rc,
+};
 
-use crate::{lang_error::LangError, object::Object, scanner::token::Token};
+use crate::{
+    gc::{self, Trace, Tracer},
+    lang_error::LangError,
+    object::Object,
+    scanner::token::Token,
+};
 
 use super::callable::lox_class::LoxClass;
 
 #[derive(Debug, Clone)]
 pub struct LoxInstance {
-    class: LoxClass,
+    pub class: Rc<LoxClass>,
     fields: RefCell<HashMap<String, Object>>,
+    /// Set by `freeze`, after which `Set` expressions on the instance fail
+    frozen: Cell<bool>,
 }
 
 impl LoxInstance {
-    pub fn new(class: LoxClass) -> LoxInstance {
-        LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Rc<LoxInstance> {
+        let instance = Rc::new(LoxInstance {
             class,
             fields: RefCell::new(HashMap::new()),
-        }
+            frozen: Cell::new(false),
+        });
+        gc::track_instance(&instance);
+        instance
     }
 
-    pub fn get(self: Rc<Self>, name: Token) -> Result<Object, LangError> {
+    pub fn get(self: Rc<Self>, name: &Token) -> Result<Object, LangError> {
         if let Some(v) = self.fields.borrow().get(&name.lexeme) {
             return Ok(v.clone());
         }
-        if let Some(method) = self.class.find_method(name.lexeme.clone()) {
-            let lox_function = Box::new(method.clone().bind(self));
-            let object = Object::Function(lox_function);
-            return Ok(object);
+        if let Some(method) = self.class.find_method(&name.lexeme) {
+            return Ok(Object::function(method.bind(Object::Instance(self))));
+        }
+        if name.lexeme == "class" {
+            return Ok(Object::Class(self.class.clone()));
         }
         let message = format!("Undefined property '{}'.", name.lexeme);
         Err(LangError::RuntimeError {
@@ -34,11 +50,41 @@ impl LoxInstance {
         })
     }
 
-    pub fn set(&self, name: Token, value: Object) {
-        self.fields.borrow_mut().insert(name.lexeme, value);
+    pub fn has_field(&self, name: &str) -> bool {
+        self.fields.borrow().contains_key(name)
+    }
+
+    pub fn set(&self, name: &Token, value: Object) {
+        self.fields.borrow_mut().insert(name.lexeme.clone(), value);
+    }
+
+    pub fn freeze(&self) {
+        self.frozen.set(true);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.get()
     }
 
-    pub fn to_string(&self) -> String {
-        format!("{} instance", self.class.name)
+    pub fn clear(&self) {
+        if let Ok(mut fields) = self.fields.try_borrow_mut() {
+            fields.clear();
+        }
+    }
+}
+
+impl fmt::Display for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+impl Trace for LoxInstance {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.class(&self.class);
+        match self.fields.try_borrow() {
+            Ok(fields) => fields.values().for_each(|value| value.trace(tracer)),
+            Err(_) => tracer.pin(),
+        }
     }
 }
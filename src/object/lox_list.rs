@@ -0,0 +1,36 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    gc::{self, Trace, Tracer},
+    object::Object,
+};
+
+#[derive(Debug)]
+pub struct LoxList {
+    pub elements: RefCell<Vec<Object>>,
+}
+
+impl LoxList {
+    pub fn new(elements: Vec<Object>) -> Rc<LoxList> {
+        let list = Rc::new(LoxList {
+            elements: RefCell::new(elements),
+        });
+        gc::track_list(&list);
+        list
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut elements) = self.elements.try_borrow_mut() {
+            elements.clear();
+        }
+    }
+}
+
+impl Trace for LoxList {
+    fn trace(&self, tracer: &mut Tracer) {
+        match self.elements.try_borrow() {
+            Ok(elements) => elements.iter().for_each(|element| element.trace(tracer)),
+            Err(_) => tracer.pin(),
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    gc::{self, Trace, Tracer},
+    object::callable::lox_function::LoxFunction,
+};
+
+#[derive(Debug)]
+pub struct LoxTrait {
+    pub name: String,
+    pub methods: HashMap<String, LoxFunction>,
+}
+
+impl LoxTrait {
+    pub fn new(name: String, methods: HashMap<String, LoxFunction>) -> Rc<LoxTrait> {
+        let lox_trait = Rc::new(LoxTrait { name, methods });
+        gc::track_trait(&lox_trait);
+        lox_trait
+    }
+}
+
+impl Trace for LoxTrait {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.methods
+            .values()
+            .for_each(|method| method.trace(tracer));
+    }
+}
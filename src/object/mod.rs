@@ -1,61 +1,173 @@
 pub mod callable;
 pub mod literal_type;
+pub mod lox_generator;
 pub mod lox_instance;
+pub mod lox_list;
+pub mod lox_trait;
 
-use std::{fmt::Debug, rc::Rc};
+use std::{
+    fmt::{self, Debug, Display},
+    rc::Rc,
+};
 
-use crate::{interpreter::Interpreter, lang_error::LangError};
+use crate::{
+    gc::{self, Trace, Tracer},
+    interpreter::Interpreter,
+    lang_error::LangError,
+    scanner::token::Token,
+};
 
-use self::{callable::lox_class::LoxClass, literal_type::LiteralType, lox_instance::LoxInstance};
+use self::{
+    callable::{lox_class::LoxClass, lox_function::LoxFunction},
+    literal_type::LiteralType,
+    lox_generator::LoxGenerator,
+    lox_instance::LoxInstance,
+    lox_list::LoxList,
+    lox_trait::LoxTrait,
+};
 
 /// Values and Callable Objects that a user can define
 #[derive(Debug, Clone)]
 pub enum Object {
-    Function(Box<dyn LoxCallable>),
-    Class(LoxClass),
+    Function(Rc<dyn LoxCallable>),
+    Class(Rc<LoxClass>),
     Instance(Rc<LoxInstance>),
+    Trait(Rc<LoxTrait>),
+    List(Rc<LoxList>),
+    Generator(Rc<LoxGenerator>),
     Value(LiteralType),
 }
 
 impl Object {
-    pub fn fetch_value(self) -> LiteralType {
+    pub fn function(callable: impl LoxCallable + 'static) -> Object {
+        let function: Rc<dyn LoxCallable> = Rc::new(callable);
+        gc::track_function(&function);
+        Object::Function(function)
+    }
+
+    pub fn is_truthy(&self) -> bool {
         match self {
-            Object::Value(v) => v,
-            _ => panic!("Supposed to be Value"),
+            Object::Value(value) => literal_type::is_truthy(value.clone()),
+            _ => true,
+        }
+    }
+
+    /// Values are compared by content, everything else by identity
+    pub fn equals(&self, other: &Object) -> bool {
+        match (self, other) {
+            (Object::Value(left), Object::Value(right)) => left == right,
+            (Object::Function(left), Object::Function(right)) => {
+                Rc::ptr_eq(left, right)
+                    || match (left.as_lox_function(), right.as_lox_function()) {
+                        (Some(left), Some(right)) => left.is_same(right),
+                        _ => false,
+                    }
+            }
+            (Object::Class(left), Object::Class(right)) => Rc::ptr_eq(left, right),
+            (Object::Instance(left), Object::Instance(right)) => Rc::ptr_eq(left, right),
+            (Object::Trait(left), Object::Trait(right)) => Rc::ptr_eq(left, right),
+            (Object::List(left), Object::List(right)) => Rc::ptr_eq(left, right),
+            (Object::Generator(left), Object::Generator(right)) => Rc::ptr_eq(left, right),
+            _ => false,
         }
     }
 }
 
-pub trait LoxCallable: LoxCallableClone {
-    fn arity(&self) -> usize;
-    fn call(
-        &self,
-        interpreter: &mut Interpreter,
-        arguments: Vec<LiteralType>,
-    ) -> Result<Object, LangError>;
-    fn to_string(&self) -> String;
+impl Trace for Object {
+    fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            Object::Function(func) => tracer.function(func),
+            Object::Class(class) => tracer.class(class),
+            Object::Instance(instance) => tracer.instance(instance),
+            Object::Trait(lox_trait) => tracer.lox_trait(lox_trait),
+            Object::List(list) => tracer.list(list),
+            Object::Generator(generator) => tracer.generator(generator),
+            Object::Value(_) => (),
+        }
+    }
+}
+
+/// How many arguments a callable accepts, `max` is `None` when it takes a rest parameter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub fn exact(count: usize) -> Arity {
+        Arity {
+            min: count,
+            max: Some(count),
+        }
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min && self.max.is_none_or(|max| count <= max)
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{} to {}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
 }
 
-pub trait LoxCallableClone {
-    fn clone_box(&self) -> Box<dyn LoxCallable>;
+/// Arguments of a call, the named ones always come after the positional ones
+#[derive(Debug, Default, Clone)]
+pub struct Arguments {
+    pub positional: Vec<Object>,
+    pub named: Vec<(Token, Object)>,
 }
 
-impl<T> LoxCallableClone for T
-where
-    T: 'static + LoxCallable + Clone,
-{
-    fn clone_box(&self) -> Box<dyn LoxCallable> {
-        Box::new(self.clone())
+impl Arguments {
+    pub fn count(&self) -> usize {
+        self.positional.len() + self.named.len()
+    }
+
+    /// For callables that have no parameter names to match against
+    pub fn into_positional(self) -> Result<Vec<Object>, LangError> {
+        match self.named.first() {
+            Some((name, _)) => Err(LangError::RuntimeError {
+                message: format!("Unexpected named argument '{}'.", name.lexeme),
+                line: name.line,
+            }),
+            None => Ok(self.positional),
+        }
     }
 }
 
-impl Clone for Box<dyn LoxCallable> {
-    fn clone(&self) -> Box<dyn LoxCallable> {
-        self.clone_box()
+impl From<Vec<Object>> for Arguments {
+    fn from(positional: Vec<Object>) -> Self {
+        Arguments {
+            positional,
+            named: Vec::new(),
+        }
+    }
+}
+
+pub trait LoxCallable: Trace {
+    fn arity(&self) -> Arity;
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Arguments,
+    ) -> Result<Object, LangError>;
+    fn to_string(&self) -> String;
+    /// What profiles call it
+    fn name(&self) -> String;
+
+    /// Lets a tail call to a Lox function reuse the caller's frame
+    fn as_lox_function(&self) -> Option<&LoxFunction> {
+        None
     }
 }
 
-impl Debug for Box<dyn LoxCallable> {
+impl Debug for dyn LoxCallable {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "LoxCallable")
     }
@@ -63,12 +175,56 @@ impl Debug for Box<dyn LoxCallable> {
 
 #[cfg(test)]
 mod tests {
-    use super::LoxCallable;
-    use crate::object::callable::global_function::Clock;
+    use super::{Arity, LoxCallable, Object};
+    use crate::object::{
+        callable::{global_function::Clock, lox_class::LoxClass},
+        literal_type::LiteralType,
+        lox_instance::LoxInstance,
+    };
+    use std::collections::HashMap;
 
     #[test]
     fn test_to_string() {
         let clock_function = Clock::new();
         assert_eq!(clock_function.to_string(), "native fn <Clock>")
     }
+
+    #[test]
+    fn test_equals() {
+        let one = Object::Value(LiteralType::Num(1.0));
+        assert!(one.equals(&Object::Value(LiteralType::Num(1.0))));
+        let class = LoxClass::new(
+            "Point".to_string(),
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let instance = Object::Instance(LoxInstance::new(class.clone()));
+        assert!(instance.equals(&instance.clone()));
+        assert!(!instance.equals(&Object::Instance(LoxInstance::new(class.clone()))));
+        assert!(!instance.equals(&one));
+        let clock = Object::function(Clock::new());
+        assert!(clock.equals(&clock.clone()));
+        assert!(!clock.equals(&Object::function(Clock::new())));
+    }
+
+    #[test]
+    fn test_arity() {
+        let exact = Arity::exact(2);
+        assert!(exact.accepts(2));
+        assert!(!exact.accepts(1));
+        assert_eq!(exact.to_string(), "2");
+        let range = Arity {
+            min: 1,
+            max: Some(3),
+        };
+        assert!(range.accepts(3));
+        assert!(!range.accepts(4));
+        assert_eq!(range.to_string(), "1 to 3");
+        let variadic = Arity { min: 1, max: None };
+        assert!(variadic.accepts(100));
+        assert!(!variadic.accepts(0));
+        assert_eq!(variadic.to_string(), "at least 1");
+    }
 }
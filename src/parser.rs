@@ -1,21 +1,45 @@
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use crate::expr::{
-    Assign, Binary, Call, Expr, Get, Grouping, Literal, Logical, Set, Super, This, Unary, Variable,
+    Assign, Binary, Call, Conditional, Expr, Get, Grouping, Index, Lambda, Literal, Logical, Range,
+    Set, Super, This, Tuple, Unary, Variable,
 };
 use crate::lang_error::{self, LangError};
 use crate::object::literal_type::LiteralType;
+use crate::object::lox_generator::contains_yield;
+use crate::pattern::{MatchArm, Pattern, VarPattern};
 use crate::scanner::token::{Token, TokenType};
-use crate::stmt::{Block, Class, Expression, Function, If, Print, Return, Stmt, Var, While};
+use crate::stmt::{
+    Block, Break, Class, Continue, Expression, ForIn, Function, If, Match, MultiAssign, Print,
+    Return, Stmt, Trait, Var, While, Yield,
+};
 
 #[derive(Default, Debug)]
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    /// Parsing the statement of a match arm, which can end at the arm's `,`
+    in_match_arm: bool,
+    /// Parsing the guard of a match arm, where `(...) =>` is a group followed
+    /// by the arm's `=>` rather than an arrow function
+    in_guard: bool,
 }
 
 type Statements = Vec<Stmt>;
+/// Parameter names, their default values and the rest parameter
+type Parameters = (Vec<Token>, Vec<Option<Expr>>, Option<Token>);
 
 const MAX_NUM_OF_ARGS: usize = 255;
 
+/// Ids for expressions that refer to variables. They are unique for the
+/// whole process so resolved locals stay valid across REPL lines.
+static NEXT_EXPR_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_expr_id() -> u64 {
+    NEXT_EXPR_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
         Parser {
@@ -33,13 +57,28 @@ impl Parser {
         Ok(statements)
     }
 
+    /// Parse a lone expression, such as one typed at the debugger prompt
+    pub fn parse_expression(&mut self) -> Result<Expr, LangError> {
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            lang_error::parser_error(self.peek(), "Expect end of expression.".to_string());
+            return Err(LangError::ParseError);
+        }
+        Ok(expr)
+    }
+
     fn declaration(&mut self) -> Result<Stmt, LangError> {
         let result = if self.match_token_type(&vec![TokenType::Var]) {
-            self.var_declaration()
-        } else if self.match_token_type(&vec![TokenType::Fun]) {
+            self.var_declaration(false)
+        } else if self.match_token_type(&vec![TokenType::Const]) {
+            self.var_declaration(true)
+        } else if self.check(&TokenType::Fun) && self.check_next(&TokenType::Identifier) {
+            self.advance();
             self.function("function")
         } else if self.match_token_type(&vec![TokenType::Class]) {
             self.class_declaration()
+        } else if self.match_token_type(&vec![TokenType::Trait]) {
+            self.trait_declaration()
         } else {
             self.statement()
         };
@@ -50,25 +89,68 @@ impl Parser {
         result
     }
 
-    fn var_declaration(&mut self) -> Result<Stmt, LangError> {
-        let name = self
-            .consume(TokenType::Identifier, "Expect variable name.")?
-            .clone();
+    /// `var` or, when `constant`, `const`, which must be initialized
+    fn var_declaration(&mut self, constant: bool) -> Result<Stmt, LangError> {
+        let (names, pattern) = if self.match_token_type(&vec![TokenType::LeftBracket]) {
+            (self.var_names(TokenType::RightBracket)?, VarPattern::List)
+        } else if self.match_token_type(&vec![TokenType::LeftBrace]) {
+            (self.var_names(TokenType::RightBrace)?, VarPattern::Fields)
+        } else {
+            let name = self
+                .consume(TokenType::Identifier, "Expect variable name.")?
+                .clone();
+            (vec![name], VarPattern::Name)
+        };
 
+        // Only a list pattern takes several values, as in `var [a, b] = 1, 2;`
         let initializer = if self.match_token_type(&vec![TokenType::Equal]) {
-            self.expression()?
-        } else {
+            if pattern == VarPattern::List {
+                self.tuple()?
+            } else {
+                let value = self.expression()?;
+                if self.check(&TokenType::Comma) {
+                    let error_message =
+                        "Only a '[...]' pattern can take several values.".to_string();
+                    lang_error::parser_error(self.peek(), error_message);
+                    return Err(LangError::ParseError);
+                }
+                value
+            }
+        } else if constant {
+            lang_error::parser_error(
+                self.peek(),
+                "Expect '=' to initialize constant.".to_string(),
+            );
+            return Err(LangError::ParseError);
+        } else if pattern == VarPattern::Name {
             Expr::Literal(Literal::new(LiteralType::Nil))
+        } else {
+            lang_error::parser_error(self.peek(), "Expect '=' to destructure.".to_string());
+            return Err(LangError::ParseError);
         };
 
         self.consume(
             TokenType::Semicolon,
             "Expect ';' after variable declaration.",
         )?;
-        let var = Var::new(name, initializer);
+        let var = Var::new(names, pattern, initializer, constant);
         Ok(Stmt::Var(var))
     }
 
+    /// The names inside `var [...]` or `var {...}`, up to `close`
+    fn var_names(&mut self, close: TokenType) -> Result<Vec<Token>, LangError> {
+        let mut names = Vec::new();
+        loop {
+            let name = self.consume(TokenType::Identifier, "Expect variable name.")?;
+            names.push(name.clone());
+            if !self.match_token_type(&vec![TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(close, "Expect end of destructuring pattern.")?;
+        Ok(names)
+    }
+
     fn class_declaration(&mut self) -> Result<Stmt, LangError> {
         let name = self
             .consume(TokenType::Identifier, "Expect class name.")?
@@ -76,22 +158,70 @@ impl Parser {
 
         let superclass = if self.match_token_type(&vec![TokenType::Less]) {
             self.consume(TokenType::Identifier, "Expect superclass name.")?;
-            Some(Variable::new(self.previous().clone()))
+            Some(Variable::new(next_expr_id(), self.previous().clone()))
         } else {
             None
         };
+        let mut traits = Vec::new();
+        if self.match_token_type(&vec![TokenType::With]) {
+            loop {
+                self.consume(TokenType::Identifier, "Expect trait name.")?;
+                traits.push(Variable::new(next_expr_id(), self.previous().clone()));
+                if !self.match_token_type(&vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
         self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
 
-        let mut methods: Vec<Function> = Vec::new();
+        let mut methods: Vec<Rc<Function>> = Vec::new();
+        let mut class_methods: Vec<Rc<Function>> = Vec::new();
+        let mut getters: Vec<Rc<Function>> = Vec::new();
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
-            match self.function("method")? {
-                Stmt::Function(method) => methods.push(method),
-                _ => panic!("Supposed to be a method"),
+            if self.match_token_type(&vec![TokenType::Class]) {
+                class_methods.push(self.method()?);
+            } else if self.check_next(&TokenType::LeftBrace) {
+                getters.push(self.getter()?);
+            } else {
+                methods.push(self.method()?);
             }
         }
 
         self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
-        Ok(Stmt::Class(Class::new(name, superclass, methods)))
+        let class = Class::new(name, superclass, traits, methods, class_methods, getters);
+        Ok(Stmt::Class(class))
+    }
+
+    fn trait_declaration(&mut self) -> Result<Stmt, LangError> {
+        let name = self
+            .consume(TokenType::Identifier, "Expect trait name.")?
+            .clone();
+        self.consume(TokenType::LeftBrace, "Expect '{' before trait body.")?;
+
+        let mut methods: Vec<Rc<Function>> = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(self.method()?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after trait body.")?;
+        Ok(Stmt::Trait(Trait::new(name, methods)))
+    }
+
+    fn method(&mut self) -> Result<Rc<Function>, LangError> {
+        match self.function("method")? {
+            Stmt::Function(method) => Ok(method),
+            _ => panic!("Supposed to be a method"),
+        }
+    }
+
+    fn getter(&mut self) -> Result<Rc<Function>, LangError> {
+        let name = self
+            .consume(TokenType::Identifier, "Expect getter name.")?
+            .clone();
+        self.consume(TokenType::LeftBrace, "Expect '{' before getter body.")?;
+        let body = self.block()?;
+        let getter = new_function(name, (Vec::new(), Vec::new(), None), body);
+        Ok(Rc::new(getter))
     }
 
     fn function(&mut self, kind: &str) -> Result<Stmt, LangError> {
@@ -99,7 +229,20 @@ impl Parser {
         let name = self.consume(TokenType::Identifier, &message)?.clone();
         let message = format!("Expect '(' after {} name.", kind);
         self.consume(TokenType::LeftParen, &message)?;
+        let parameters = self.parameters()?;
+
+        let message = format!("Expect '{{' before {} body.", kind);
+        self.consume(TokenType::LeftBrace, &message)?;
+        let body = self.block()?;
+        let function = new_function(name, parameters, body);
+        Ok(Stmt::Function(Rc::new(function)))
+    }
+
+    /// Parameter list of a function, after the opening parenthesis
+    fn parameters(&mut self) -> Result<Parameters, LangError> {
         let mut parameters = Vec::new();
+        let mut defaults = Vec::new();
+        let mut rest = None;
         if !self.check(&TokenType::RightParen) {
             loop {
                 if parameters.len() >= MAX_NUM_OF_ARGS {
@@ -107,19 +250,111 @@ impl Parser {
                         format!("Can't have more than {} parameters.", MAX_NUM_OF_ARGS);
                     lang_error::parser_error(self.peek(), error_message);
                 }
-                let parameter = self.consume(TokenType::Identifier, "Expect parameter name.")?;
-                parameters.push(parameter.clone());
+                if self.match_token_type(&vec![TokenType::DotDotDot]) {
+                    let parameter =
+                        self.consume(TokenType::Identifier, "Expect rest parameter name.")?;
+                    rest = Some(parameter.clone());
+                    if self.check(&TokenType::Comma) {
+                        let error_message = "Rest parameter must be the last one.".to_string();
+                        lang_error::parser_error(self.peek(), error_message);
+                        return Err(LangError::ParseError);
+                    }
+                    break;
+                }
+                let parameter = self
+                    .consume(TokenType::Identifier, "Expect parameter name.")?
+                    .clone();
+                let default = if self.match_token_type(&vec![TokenType::Equal]) {
+                    Some(self.expression()?)
+                } else {
+                    if defaults.iter().any(Option::is_some) {
+                        let error_message =
+                            "Parameter without a default can't follow one with a default."
+                                .to_string();
+                        lang_error::parser_error(&parameter, error_message);
+                        return Err(LangError::ParseError);
+                    }
+                    None
+                };
+                parameters.push(parameter);
+                defaults.push(default);
                 if !self.match_token_type(&vec![TokenType::Comma]) {
                     break;
                 }
             }
         }
         self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+        Ok((parameters, defaults, rest))
+    }
 
-        let message = format!("Expect '{{' before {} body.", kind);
-        self.consume(TokenType::LeftBrace, &message)?;
+    /// The value of the number just consumed, negated after a `-`. The scanner
+    /// leaves integers too large for i64 without a value, and the only one
+    /// allowed is 9223372036854775808 negated to i64::MIN.
+    fn number_literal(&self, negated: bool) -> Result<LiteralType, LangError> {
+        let token = self.previous();
+        match &token.literal {
+            Some(literal) if negated => Ok(-literal.clone()),
+            Some(literal) => Ok(literal.clone()),
+            None if negated && token.lexeme.parse() == Ok(i64::MIN.unsigned_abs()) => {
+                Ok(LiteralType::Int(i64::MIN))
+            }
+            None => {
+                lang_error::parser_error(token, "Integer literal too large.".to_string());
+                Err(LangError::ParseError)
+            }
+        }
+    }
+
+    fn lambda(&mut self) -> Result<Expr, LangError> {
+        let name = Token {
+            lexeme: "lambda".to_string(),
+            ..self.previous().clone()
+        };
+        self.consume(TokenType::LeftParen, "Expect '(' after 'fun'.")?;
+        let parameters = self.parameters()?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body.")?;
         let body = self.block()?;
-        Ok(Stmt::Function(Function::new(name, parameters, body)))
+        let function = new_function(name, parameters, body);
+        Ok(Expr::Lambda(Lambda::new(Rc::new(function))))
+    }
+
+    /// `(a, b) => a + b`, with the opening parenthesis already consumed
+    fn arrow_function(&mut self) -> Result<Expr, LangError> {
+        let parameters = self.parameters()?;
+        let keyword = self
+            .consume(TokenType::Arrow, "Expect '=>' after parameters.")?
+            .clone();
+        let body = if self.match_token_type(&vec![TokenType::LeftBrace]) {
+            self.block()?
+        } else {
+            let value = self.expression()?;
+            vec![Stmt::Return(Return::new(keyword.clone(), value))]
+        };
+        let name = Token {
+            lexeme: "lambda".to_string(),
+            ..keyword
+        };
+        let function = new_function(name, parameters, body);
+        Ok(Expr::Lambda(Lambda::new(Rc::new(function))))
+    }
+
+    /// Whether the parenthesis just consumed opens the parameters of an arrow function
+    fn is_arrow_function(&self) -> bool {
+        let mut depth = 0;
+        for (offset, token) in self.tokens[self.current..].iter().enumerate() {
+            match token.token_type {
+                TokenType::LeftParen => depth += 1,
+                TokenType::RightParen if depth == 0 => {
+                    return matches!(
+                        self.tokens.get(self.current + offset + 1),
+                        Some(token) if token.token_type == TokenType::Arrow
+                    );
+                }
+                TokenType::RightParen => depth -= 1,
+                _ => (),
+            }
+        }
+        false
     }
 
     fn statement(&mut self) -> Result<Stmt, LangError> {
@@ -138,6 +373,22 @@ impl Parser {
         if self.match_token_type(&vec![TokenType::While]) {
             return self.while_statement();
         }
+        if self.match_token_type(&vec![TokenType::Yield]) {
+            return self.yield_statement();
+        }
+        if self.match_token_type(&vec![TokenType::Match]) {
+            return self.match_statement();
+        }
+        if self.match_token_type(&vec![TokenType::Break]) {
+            let keyword = self.previous().clone();
+            self.consume_semicolon("Expect ';' after 'break'.")?;
+            return Ok(Stmt::Break(Break::new(keyword)));
+        }
+        if self.match_token_type(&vec![TokenType::Continue]) {
+            let keyword = self.previous().clone();
+            self.consume_semicolon("Expect ';' after 'continue'.")?;
+            return Ok(Stmt::Continue(Continue::new(keyword)));
+        }
         if self.match_token_type(&vec![TokenType::LeftBrace]) {
             let statements = self.block()?;
             let block = Stmt::Block(Block::new(statements));
@@ -146,6 +397,119 @@ impl Parser {
         self.expression_statement()
     }
 
+    fn match_statement(&mut self) -> Result<Stmt, LangError> {
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'match'.")?;
+        let value = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after value.")?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before match arms.")?;
+        let mut arms = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let pattern = self.pattern()?;
+            let guard = if self.match_token_type(&vec![TokenType::If]) {
+                self.in_guard = true;
+                let guard = self.expression();
+                self.in_guard = false;
+                Some(guard?)
+            } else {
+                None
+            };
+            let arrow = self
+                .consume(TokenType::Arrow, "Expect '=>' after pattern.")?
+                .clone();
+            self.in_match_arm = true;
+            let body = self.statement();
+            self.in_match_arm = false;
+            let body = Box::new(body?);
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                arrow,
+                body,
+            });
+            // Arms may be separated by commas, which read better after blocks
+            self.match_token_type(&vec![TokenType::Comma]);
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after match arms.")?;
+        Ok(Stmt::Match(Match::new(keyword, value, arms)))
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, LangError> {
+        let mut alternatives = vec![self.single_pattern()?];
+        while self.match_token_type(&vec![TokenType::Pipe]) {
+            alternatives.push(self.single_pattern()?);
+        }
+        if alternatives.len() == 1 {
+            return Ok(alternatives.pop().unwrap());
+        }
+        Ok(Pattern::Alternatives(alternatives))
+    }
+
+    fn single_pattern(&mut self) -> Result<Pattern, LangError> {
+        let literal_types = vec![
+            TokenType::Number,
+            TokenType::LString,
+            TokenType::True,
+            TokenType::False,
+            TokenType::Nil,
+        ];
+        if self.match_token_type(&literal_types) {
+            let literal = match self.previous().token_type {
+                TokenType::True => LiteralType::True,
+                TokenType::False => LiteralType::False,
+                TokenType::Nil => LiteralType::Nil,
+                TokenType::Number => self.number_literal(false)?,
+                _ => self.previous().literal.clone().unwrap(),
+            };
+            return Ok(Pattern::Literal(literal));
+        }
+        if self.match_token_type(&vec![TokenType::Minus]) {
+            self.consume(TokenType::Number, "Expect number after '-'.")?;
+            return Ok(Pattern::Literal(self.number_literal(true)?));
+        }
+        let name = self
+            .consume(TokenType::Identifier, "Expect pattern.")?
+            .clone();
+        if self.match_token_type(&vec![TokenType::LeftParen]) {
+            return self.instance_pattern(name);
+        }
+        if name.lexeme == "_" {
+            return Ok(Pattern::Wildcard);
+        }
+        Ok(Pattern::Binding(name))
+    }
+
+    /// The fields of `Class(...)`, positional ones first like call arguments
+    fn instance_pattern(&mut self, class: Token) -> Result<Pattern, LangError> {
+        let mut positional = Vec::new();
+        let mut named = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if self.check(&TokenType::Identifier) && self.check_next(&TokenType::Colon) {
+                    let field = self.advance().clone();
+                    self.advance();
+                    named.push((field, self.pattern()?));
+                } else if !named.is_empty() {
+                    let error_message =
+                        "Positional patterns must come before named patterns.".to_string();
+                    lang_error::parser_error(self.peek(), error_message);
+                    return Err(LangError::ParseError);
+                } else {
+                    positional.push(self.pattern()?);
+                }
+                if !self.match_token_type(&vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after fields.")?;
+        Ok(Pattern::Instance {
+            class: Variable::new(next_expr_id(), class),
+            positional,
+            named,
+        })
+    }
+
     fn if_statement(&mut self) -> Result<Stmt, LangError> {
         self.consume(TokenType::LeftParen, "Expect '(' after value.")?;
         let condition = self.expression()?;
@@ -161,11 +525,20 @@ impl Parser {
     }
 
     fn for_statement(&mut self) -> Result<Stmt, LangError> {
+        let keyword = self.previous().clone();
         self.consume(TokenType::LeftParen, "Expect '(' after value.")?;
+        // The loop variable is always new, so `var` is optional
+        let declares = self.check(&TokenType::Var);
+        if declares {
+            self.advance();
+        }
+        if self.check(&TokenType::Identifier) && self.check_next(&TokenType::In) {
+            return self.for_in_statement(keyword);
+        }
         let initializer = if self.match_token_type(&vec![TokenType::Semicolon]) {
             None
-        } else if self.match_token_type(&vec![TokenType::Var]) {
-            Some(self.var_declaration()?)
+        } else if declares {
+            Some(self.var_declaration(false)?)
         } else {
             Some(self.expression_statement()?)
         };
@@ -182,14 +555,8 @@ impl Parser {
         };
         self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
         let body = self.statement()?;
-        let for_loop_with_increment = if let Some(expr) = increment {
-            let statements = vec![body, Stmt::Expression(Expression::new(expr))];
-            let block = Block::new(statements);
-            Stmt::Block(block)
-        } else {
-            body
-        };
-        let while_loop = While::new(condition, Box::new(for_loop_with_increment));
+        // The increment lives on the loop so that `continue` still runs it
+        let while_loop = While::new(keyword, condition, Box::new(body), increment);
         let for_loop_with_condition = Stmt::While(while_loop);
         let for_loop_with_initializer = if let Some(statement) = initializer {
             let statements = vec![statement, for_loop_with_condition];
@@ -202,41 +569,69 @@ impl Parser {
         Ok(for_loop_with_initializer)
     }
 
+    /// The rest of `for (name in iterable) body`, after the '('
+    fn for_in_statement(&mut self, keyword: Token) -> Result<Stmt, LangError> {
+        let name = self.advance().clone();
+        self.advance();
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::ForIn(ForIn::new(keyword, name, iterable, body)))
+    }
+
     fn print_statement(&mut self) -> Result<Stmt, LangError> {
+        let keyword = self.previous().clone();
         let value = self.expression()?;
-        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
-        Ok(Stmt::Print(Print::new(value)))
+        self.consume_semicolon("Expect ';' after value.")?;
+        Ok(Stmt::Print(Print::new(keyword, value)))
     }
 
     fn return_statement(&mut self) -> Result<Stmt, LangError> {
         let keyword = self.previous().clone();
-        let value = if self.check(&TokenType::Semicolon) {
+        let value = if self.at_statement_end() {
             let nil = Expr::Literal(Literal::new(LiteralType::Nil));
             Ok(nil)
         } else {
-            self.expression()
+            self.tuple()
         }?;
 
-        self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+        self.consume_semicolon("Expect ';' after return value.")?;
         Ok(Stmt::Return(Return::new(keyword, value)))
     }
 
+    fn yield_statement(&mut self) -> Result<Stmt, LangError> {
+        let keyword = self.previous().clone();
+        let value = if self.at_statement_end() {
+            Expr::Literal(Literal::new(LiteralType::Nil))
+        } else {
+            self.tuple()?
+        };
+
+        self.consume_semicolon("Expect ';' after yield value.")?;
+        Ok(Stmt::Yield(Yield::new(keyword, value)))
+    }
+
     fn while_statement(&mut self) -> Result<Stmt, LangError> {
+        let keyword = self.previous().clone();
         self.consume(TokenType::LeftParen, "Expect '(' after value.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after value.")?;
         let body = Box::new(self.statement()?);
 
-        Ok(Stmt::While(While::new(condition, body)))
+        Ok(Stmt::While(While::new(keyword, condition, body, None)))
     }
 
     fn block(&mut self) -> Result<Statements, LangError> {
         let mut statements = Vec::new();
+        let in_match_arm = std::mem::replace(&mut self.in_match_arm, false);
+        let in_guard = std::mem::replace(&mut self.in_guard, false);
 
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
             let declaration = self.declaration()?;
             statements.push(declaration);
         }
+        self.in_match_arm = in_match_arm;
+        self.in_guard = in_guard;
 
         self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
         Ok(statements)
@@ -244,41 +639,104 @@ impl Parser {
 
     fn expression_statement(&mut self) -> Result<Stmt, LangError> {
         let value = self.expression()?;
-        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        if self.check(&TokenType::Comma) && !self.in_match_arm {
+            return self.multi_assignment(value);
+        }
+        self.consume_semicolon("Expect ';' after value.")?;
         Ok(Stmt::Expression(Expression::new(value)))
     }
 
+    /// `a, b = b, a;`, which evaluates the whole right side before assigning
+    fn multi_assignment(&mut self, first: Expr) -> Result<Stmt, LangError> {
+        let mut targets = vec![first];
+        while self.match_token_type(&vec![TokenType::Comma]) {
+            targets.push(self.call()?);
+        }
+        let equals = self
+            .consume(TokenType::Equal, "Expect '=' after assignment targets.")?
+            .clone();
+        for target in targets.iter() {
+            check_assignable(target, &equals, "Invalid assignment target.")?;
+        }
+        let value = self.tuple()?;
+        self.consume_semicolon("Expect ';' after value.")?;
+        Ok(Stmt::MultiAssign(MultiAssign::new(targets, equals, value)))
+    }
+
+    /// An expression, or several separated by commas which make a list, as on
+    /// the right of `var [a, b] = 1, 2;` and `return a, b;`
+    fn tuple(&mut self) -> Result<Expr, LangError> {
+        let first = self.expression()?;
+        // In a match arm the comma ends the arm instead
+        if !self.check(&TokenType::Comma) || self.in_match_arm {
+            return Ok(first);
+        }
+        let mut elements = vec![first];
+        while self.match_token_type(&vec![TokenType::Comma]) {
+            elements.push(self.expression()?);
+        }
+        Ok(Expr::Tuple(Tuple::new(elements)))
+    }
+
     fn expression(&mut self) -> Result<Expr, LangError> {
         self.assignment()
     }
 
     fn assignment(&mut self) -> Result<Expr, LangError> {
-        let expr = self.or()?;
-
-        if self.match_token_type(&vec![TokenType::Equal]) {
+        let expr = self.conditional()?;
+
+        let compound_types = vec![
+            TokenType::PlusEqual,
+            TokenType::MinusEqual,
+            TokenType::StarEqual,
+            TokenType::SlashEqual,
+            TokenType::PercentEqual,
+        ];
+        if self.match_token_type(&vec![TokenType::Equal]) || self.match_token_type(&compound_types)
+        {
             let equals = self.previous().clone();
+            let operator = compound_operator(&equals);
             let value = self.assignment()?;
-
-            match expr {
-                Expr::Variable(var) => {
-                    let name = var.name;
-                    let assign = Expr::Assign(Assign::new(name, Box::new(value)));
-                    return Ok(assign);
-                }
-                Expr::Get(get) => {
-                    let set = Set::new(get.object, get.name, Box::new(value));
-                    return Ok(Expr::Set(set));
-                }
-                _ => {
-                    lang_error::parser_error(&equals, "Expect expression.".to_string());
-                    return Err(LangError::ParseError);
-                }
-            }
+            return assignment_target(expr, &equals, operator, value, false);
         }
 
         Ok(expr)
     }
 
+    fn conditional(&mut self) -> Result<Expr, LangError> {
+        let condition = self.coalesce()?;
+        if !self.match_token_type(&vec![TokenType::Question]) {
+            return Ok(condition);
+        }
+        let then_branch = self.expression()?;
+        self.consume(TokenType::Colon, "Expect ':' after then branch of '?'.")?;
+        // Right-associative, so `a ? b : c ? d : e` chains like `else if`
+        let else_branch = self.conditional()?;
+        let conditional = Conditional::new(
+            Box::new(condition),
+            Box::new(then_branch),
+            Box::new(else_branch),
+        );
+        Ok(Expr::Conditional(conditional))
+    }
+
+    fn coalesce(&mut self) -> Result<Expr, LangError> {
+        let token_types = vec![TokenType::QuestionQuestion];
+        self.generate_logical_expr(token_types, Parser::range)
+    }
+
+    /// `start..end`, which doesn't chain
+    fn range(&mut self) -> Result<Expr, LangError> {
+        let start = self.or()?;
+        if self.match_token_type(&vec![TokenType::DotDot]) {
+            let operator = self.previous().clone();
+            let end = self.or()?;
+            let range = Range::new(Box::new(start), operator, Box::new(end));
+            return Ok(Expr::Range(range));
+        }
+        Ok(start)
+    }
+
     fn or(&mut self) -> Result<Expr, LangError> {
         let token_types = vec![TokenType::Or];
         self.generate_logical_expr(token_types, Parser::and)
@@ -300,7 +758,30 @@ impl Parser {
             TokenType::GreaterEqual,
             TokenType::Less,
             TokenType::LessEqual,
+            TokenType::Is,
         ];
+        self.generate_binary_expr(token_types, Parser::bit_or)
+    }
+
+    // Bitwise operators bind tighter than comparisons, so `a & b == 0`
+    // tests the masked bits
+    fn bit_or(&mut self) -> Result<Expr, LangError> {
+        let token_types = vec![TokenType::Pipe];
+        self.generate_binary_expr(token_types, Parser::bit_xor)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, LangError> {
+        let token_types = vec![TokenType::Caret];
+        self.generate_binary_expr(token_types, Parser::bit_and)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, LangError> {
+        let token_types = vec![TokenType::Ampersand];
+        self.generate_binary_expr(token_types, Parser::shift)
+    }
+
+    fn shift(&mut self) -> Result<Expr, LangError> {
+        let token_types = vec![TokenType::LessLess, TokenType::GreaterGreater];
         self.generate_binary_expr(token_types, Parser::term)
     }
 
@@ -310,20 +791,52 @@ impl Parser {
     }
 
     fn factor(&mut self) -> Result<Expr, LangError> {
-        let token_types = vec![TokenType::Star, TokenType::Slash];
+        let token_types = vec![TokenType::Star, TokenType::Slash, TokenType::Percent];
         self.generate_binary_expr(token_types, Parser::unary)
     }
 
     fn unary(&mut self) -> Result<Expr, LangError> {
-        let token_types = vec![TokenType::Bang, TokenType::Minus];
+        let token_types = vec![TokenType::Bang, TokenType::Minus, TokenType::Tilde];
         if self.match_token_type(&token_types) {
             let operator = self.previous().clone();
+            let too_large = self.check(&TokenType::Number) && self.peek().literal.is_none();
+            if operator.token_type == TokenType::Minus && too_large {
+                self.advance();
+                return Ok(Expr::Literal(Literal::new(self.number_literal(true)?)));
+            }
             let right = self.unary()?;
             let unary = Unary::new(operator, Box::new(right));
             return Ok(Expr::Unary(unary));
         }
+        if self.match_token_type(&vec![TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let increment = self.previous().clone();
+            let target = self.unary()?;
+            return increment_target(target, &increment, false);
+        }
+
+        self.power()
+    }
+
+    // Right-associative, and binds tighter than a unary minus on its left
+    // so that `-2 ** 2` is -4
+    fn power(&mut self) -> Result<Expr, LangError> {
+        let base = self.postfix()?;
+        if self.match_token_type(&vec![TokenType::StarStar]) {
+            let operator = self.previous().clone();
+            let exponent = self.unary()?;
+            let binary = Binary::new(Box::new(base), operator, Box::new(exponent));
+            return Ok(Expr::Binary(binary));
+        }
+        Ok(base)
+    }
 
-        self.call()
+    fn postfix(&mut self) -> Result<Expr, LangError> {
+        let expr = self.call()?;
+        if self.match_token_type(&vec![TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let increment = self.previous().clone();
+            return increment_target(expr, &increment, true);
+        }
+        Ok(expr)
     }
 
     fn call(&mut self) -> Result<Expr, LangError> {
@@ -333,11 +846,21 @@ impl Parser {
         loop {
             if self.match_token_type(&vec![TokenType::LeftParen]) {
                 expr = self.finish_call(expr.clone())?;
-            } else if self.match_token_type(&vec![TokenType::Dot]) {
-                let name =
-                    self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
-                let get_expression = Get::new(Box::new(expr.clone()), name.clone());
+            } else if self.match_token_type(&vec![TokenType::Dot, TokenType::QuestionDot]) {
+                let optional = self.previous().token_type == TokenType::QuestionDot;
+                let name = if self.match_token_type(&vec![TokenType::Class]) {
+                    self.previous()
+                } else {
+                    self.consume(TokenType::Identifier, "Expect property name after '.'.")?
+                };
+                let get_expression = Get::new(Box::new(expr.clone()), name.clone(), optional);
                 expr = Expr::Get(get_expression);
+            } else if self.match_token_type(&vec![TokenType::LeftBracket]) {
+                let bracket = self.previous().clone();
+                let index = self.expression()?;
+                self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+                let index_expression = Index::new(Box::new(expr.clone()), bracket, Box::new(index));
+                expr = Expr::Index(index_expression);
             } else {
                 break;
             }
@@ -348,8 +871,28 @@ impl Parser {
 
     fn finish_call(&mut self, callee: Expr) -> Result<Expr, LangError> {
         let mut arguments = Vec::new();
+        let mut names = Vec::new();
+        let in_guard = std::mem::replace(&mut self.in_guard, false);
         if !self.check(&TokenType::RightParen) {
             loop {
+                if self.check(&TokenType::Identifier) && self.check_next(&TokenType::Colon) {
+                    let name = self.advance().clone();
+                    if names
+                        .iter()
+                        .any(|other: &Token| other.lexeme == name.lexeme)
+                    {
+                        let error_message = format!("Duplicate named argument '{}'.", name.lexeme);
+                        lang_error::parser_error(&name, error_message);
+                        return Err(LangError::ParseError);
+                    }
+                    names.push(name);
+                    self.advance();
+                } else if !names.is_empty() {
+                    let error_message =
+                        "Positional arguments must come before named arguments.".to_string();
+                    lang_error::parser_error(self.peek(), error_message);
+                    return Err(LangError::ParseError);
+                }
                 let argument = self.expression()?;
                 arguments.push(argument);
                 if !self.match_token_type(&vec![TokenType::Comma]) {
@@ -361,8 +904,9 @@ impl Parser {
             let error_message = format!("Can't have more than {} arguments", MAX_NUM_OF_ARGS);
             lang_error::parser_error(self.peek(), error_message);
         }
+        self.in_guard = in_guard;
         let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
-        let call = Call::new(Box::new(callee), paren.clone(), arguments);
+        let call = Call::new(Box::new(callee), paren.clone(), arguments, names);
         Ok(Expr::Call(call))
     }
 
@@ -380,14 +924,26 @@ impl Parser {
             return Ok(Expr::Literal(literal));
         }
 
-        if self.match_token_type(&vec![TokenType::Number, TokenType::LString]) {
+        if self.match_token_type(&vec![TokenType::Number]) {
+            return Ok(Expr::Literal(Literal::new(self.number_literal(false)?)));
+        }
+        if self.match_token_type(&vec![TokenType::LString]) {
             let value = self.previous().clone().literal.unwrap();
             let literal = Literal::new(value);
             return Ok(Expr::Literal(literal));
         }
 
+        if self.match_token_type(&vec![TokenType::Fun]) {
+            return self.lambda();
+        }
+
         if self.match_token_type(&vec![TokenType::LeftParen]) {
+            if !self.in_guard && self.is_arrow_function() {
+                return self.arrow_function();
+            }
+            let in_guard = std::mem::replace(&mut self.in_guard, false);
             let expr = self.expression()?;
+            self.in_guard = in_guard;
             self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
             let grouping = Grouping::new(Box::new(expr));
             return Ok(Expr::Grouping(grouping));
@@ -397,17 +953,21 @@ impl Parser {
             let keyword = self.previous().clone();
             self.consume(TokenType::Dot, "Expect '.' after 'super',")?;
             let method = self.consume(TokenType::Identifier, "Expect superclass method name.")?;
-            return Ok(Expr::Super(Super::new(keyword.clone(), method.clone())));
+            return Ok(Expr::Super(Super::new(
+                next_expr_id(),
+                keyword.clone(),
+                method.clone(),
+            )));
         }
 
         if self.match_token_type(&vec![TokenType::This]) {
             let this_token = self.previous().clone();
-            return Ok(Expr::This(This::new(this_token)));
+            return Ok(Expr::This(This::new(next_expr_id(), this_token)));
         }
 
         if self.match_token_type(&vec![TokenType::Identifier]) {
             let name = self.previous().clone();
-            let var = Variable::new(name);
+            let var = Variable::new(next_expr_id(), name);
             return Ok(Expr::Variable(var));
         }
 
@@ -463,7 +1023,14 @@ impl Parser {
         if self.is_at_end() {
             return false;
         }
-        return self.peek().token_type == *token_type;
+        self.peek().token_type == *token_type
+    }
+
+    fn check_next(&self, token_type: &TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => token.token_type == *token_type,
+            None => false,
+        }
     }
 
     fn advance(&mut self) -> &Token {
@@ -485,6 +1052,22 @@ impl Parser {
         self.tokens.get(self.current - 1).unwrap()
     }
 
+    /// Whether the current statement ends here
+    fn at_statement_end(&self) -> bool {
+        self.check(&TokenType::Semicolon)
+            || self.in_match_arm
+                && (self.check(&TokenType::Comma) || self.check(&TokenType::RightBrace))
+    }
+
+    /// The `;` after a statement, which the statement of a match arm can leave
+    /// out before the `,` or `}` after the arm
+    fn consume_semicolon(&mut self, message: &str) -> Result<(), LangError> {
+        if self.check(&TokenType::Semicolon) || !self.at_statement_end() {
+            self.consume(TokenType::Semicolon, message)?;
+        }
+        Ok(())
+    }
+
     fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&Token, LangError> {
         if self.check(&token_type) {
             return Ok(self.advance());
@@ -503,15 +1086,117 @@ impl Parser {
             }
             match self.peek().token_type {
                 TokenType::Class
+                | TokenType::Trait
                 | TokenType::Fun
                 | TokenType::Var
+                | TokenType::Const
                 | TokenType::For
                 | TokenType::If
                 | TokenType::While
                 | TokenType::Print
-                | TokenType::Return => return,
+                | TokenType::Return
+                | TokenType::Yield => return,
                 _ => self.advance(),
             };
         }
     }
 }
+
+/// A function declaration, which is a generator when its body yields
+fn new_function(name: Token, parameters: Parameters, body: Vec<Stmt>) -> Function {
+    let (params, defaults, rest) = parameters;
+    let generator = body.iter().any(contains_yield);
+    Function::new(name, params, defaults, rest, body, generator)
+}
+
+/// The binary operator a compound assignment like `+=` applies, `None` for `=`
+fn compound_operator(equals: &Token) -> Option<Token> {
+    let (token_type, lexeme) = match equals.token_type {
+        TokenType::PlusEqual | TokenType::PlusPlus => (TokenType::Plus, "+"),
+        TokenType::MinusEqual | TokenType::MinusMinus => (TokenType::Minus, "-"),
+        TokenType::StarEqual => (TokenType::Star, "*"),
+        TokenType::SlashEqual => (TokenType::Slash, "/"),
+        TokenType::PercentEqual => (TokenType::Percent, "%"),
+        _ => return None,
+    };
+    Some(Token::new(
+        token_type,
+        lexeme.to_string(),
+        None,
+        equals.line,
+    ))
+}
+
+/// Turn the left side of an assignment into an `Assign` or `Set`, which
+/// evaluate the target once even when `operator` also reads it
+fn assignment_target(
+    target: Expr,
+    equals: &Token,
+    operator: Option<Token>,
+    value: Expr,
+    postfix: bool,
+) -> Result<Expr, LangError> {
+    check_assignable(&target, equals, "Expect expression.")?;
+    let expr = match target {
+        Expr::Variable(var) => Expr::Assign(Assign::new(
+            var.id,
+            var.name,
+            operator,
+            Box::new(value),
+            postfix,
+        )),
+        Expr::Get(get) => Expr::Set(Set::new(
+            get.object,
+            get.name,
+            operator,
+            Box::new(value),
+            postfix,
+        )),
+        _ => unreachable!("checked by check_assignable"),
+    };
+    Ok(expr)
+}
+
+/// Only variables and fields can be assigned to, and not fields after a `?.`,
+/// which might not have an object to set on
+fn check_assignable(target: &Expr, token: &Token, message: &str) -> Result<(), LangError> {
+    let message = if in_optional_chain(target) {
+        "Can't assign to an optional chain."
+    } else if matches!(target, Expr::Variable(_) | Expr::Get(_)) {
+        return Ok(());
+    } else {
+        message
+    };
+    lang_error::parser_error(token, message.to_string());
+    Err(LangError::ParseError)
+}
+
+/// Whether a `?.` comes anywhere in a chain of property accesses, indexes and calls
+fn in_optional_chain(expr: &Expr) -> bool {
+    match expr {
+        Expr::Get(get) => get.optional || in_optional_chain(&get.object),
+        Expr::Index(index) => in_optional_chain(&index.object),
+        Expr::Call(call) => in_optional_chain(&call.callee),
+        _ => false,
+    }
+}
+
+/// `x++` and `++x` are `x += 1`, except that the postfix form evaluates to the old value
+fn increment_target(target: Expr, increment: &Token, postfix: bool) -> Result<Expr, LangError> {
+    check_assignable(&target, increment, "Invalid increment target.")?;
+    let operator = compound_operator(increment);
+    let one = Expr::Literal(Literal::new(LiteralType::Int(1)));
+    assignment_target(target, increment, operator, one, postfix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_expr_id() {
+        let first = next_expr_id();
+        let second = next_expr_id();
+        assert!(second > first);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::expr::{Expr, Variable};
+use crate::object::literal_type::LiteralType;
+use crate::scanner::token::Token;
+use crate::stmt::Stmt;
+
+/// What a `match` arm compares the value against
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// `_`, which matches anything
+    Wildcard,
+    /// Matches a value equal to the literal
+    Literal(LiteralType),
+    /// A name, which matches anything and binds it for the arm
+    Binding(Token),
+    /// `Point(x, y: 0)` matches instances of `Point` or its subclasses whose
+    /// fields match. Positional patterns take the fields in the order of the
+    /// parameters of `init`.
+    Instance {
+        class: Variable,
+        positional: Vec<Pattern>,
+        named: Vec<(Token, Pattern)>,
+    },
+    /// `a | b`, which can't bind names since only one side would have them
+    Alternatives(Vec<Pattern>),
+}
+
+impl Pattern {
+    /// Whether the pattern matches every value
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Binding(_) => true,
+            Pattern::Literal(_) | Pattern::Instance { .. } => false,
+            Pattern::Alternatives(alternatives) => alternatives.iter().any(Pattern::is_irrefutable),
+        }
+    }
+
+    /// The names the pattern binds, in the order it binds them
+    pub fn bindings(&self) -> Vec<&Token> {
+        match self {
+            Pattern::Wildcard | Pattern::Literal(_) => Vec::new(),
+            Pattern::Binding(name) => vec![name],
+            Pattern::Instance {
+                positional, named, ..
+            } => positional
+                .iter()
+                .chain(named.iter().map(|(_, pattern)| pattern))
+                .flat_map(Pattern::bindings)
+                .collect(),
+            Pattern::Alternatives(alternatives) => {
+                alternatives.iter().flat_map(Pattern::bindings).collect()
+            }
+        }
+    }
+
+    /// The literals the pattern matches when it is made of nothing else
+    pub fn literals(&self) -> Option<Vec<&LiteralType>> {
+        match self {
+            Pattern::Literal(literal) => Some(vec![literal]),
+            Pattern::Alternatives(alternatives) => {
+                let mut literals = Vec::new();
+                for alternative in alternatives {
+                    literals.extend(alternative.literals()?);
+                }
+                Some(literals)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// `pattern if guard => body`, where `arrow` gives the arm's line
+#[derive(Clone, Debug)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub arrow: Token,
+    pub body: Box<Stmt>,
+}
+
+/// How `var` binds its names to the value it is given
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VarPattern {
+    /// `var a = value;`
+    Name,
+    /// `var [a, b] = list;`, which needs exactly as many elements as names
+    List,
+    /// `var {x, y} = instance;`, which reads the properties with those names
+    Fields,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::token::TokenType;
+
+    #[test]
+    fn test_alternatives_of_literals() {
+        let one = Pattern::Literal(LiteralType::Int(1));
+        let two = Pattern::Literal(LiteralType::Int(2));
+        let pattern = Pattern::Alternatives(vec![one.clone(), two]);
+        assert_eq!(pattern.literals().map(|literals| literals.len()), Some(2));
+        assert!(!pattern.is_irrefutable());
+
+        let name = Token::new(TokenType::Identifier, "x".to_string(), None, 1);
+        let pattern = Pattern::Alternatives(vec![one, Pattern::Binding(name)]);
+        assert!(pattern.literals().is_none());
+        assert!(pattern.is_irrefutable());
+        assert_eq!(pattern.bindings().len(), 1);
+    }
+}
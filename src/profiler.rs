@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const SCRIPT_FRAME: &str = "<script>";
+
+#[derive(Clone, Default)]
+struct Stats {
+    calls: u64,
+    /// Time from entering to leaving, counted once for recursive entries
+    total: Duration,
+    /// Time not spent in a nested call
+    own: Duration,
+}
+
+#[derive(Clone)]
+struct Frame {
+    name: String,
+    start: Instant,
+    children: Duration,
+}
+
+/// Counts calls and wall time per Lox function and per source line, and
+/// the time spent under each call stack for flame graphs
+#[derive(Clone)]
+pub struct Profiler {
+    functions: HashMap<String, Stats>,
+    lines: HashMap<u32, Stats>,
+    /// Time spent directly in each call stack, keyed as `a;b;c`
+    folded: HashMap<String, Duration>,
+    frames: Vec<Frame>,
+    active_lines: Vec<(u32, Instant)>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            functions: HashMap::new(),
+            lines: HashMap::new(),
+            folded: HashMap::new(),
+            frames: vec![Frame {
+                name: SCRIPT_FRAME.to_string(),
+                start: Instant::now(),
+                children: Duration::ZERO,
+            }],
+            active_lines: Vec::new(),
+        }
+    }
+
+    pub fn enter_function(&mut self, name: String) {
+        self.frames.push(Frame {
+            name,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    pub fn exit_function(&mut self) {
+        let (frame, elapsed, own) = self.pop_frame();
+        let recursive = self.frames.iter().any(|f| f.name == frame.name);
+        let stats = self.functions.entry(frame.name).or_default();
+        stats.calls += 1;
+        stats.own += own;
+        if !recursive {
+            stats.total += elapsed;
+        }
+        if let Some(caller) = self.frames.last_mut() {
+            caller.children += elapsed;
+        }
+    }
+
+    pub fn enter_line(&mut self, line: u32) {
+        self.active_lines.push((line, Instant::now()));
+    }
+
+    pub fn exit_line(&mut self) {
+        let (line, start) = self.active_lines.pop().unwrap();
+        let recursive = self.active_lines.iter().any(|&(l, _)| l == line);
+        let stats = self.lines.entry(line).or_default();
+        stats.calls += 1;
+        if !recursive {
+            stats.total += start.elapsed();
+        }
+    }
+
+    /// Close the top-level frame once the script has finished
+    pub fn finish(&mut self) {
+        while self.frames.len() > 1 {
+            self.exit_function();
+        }
+        if !self.frames.is_empty() {
+            self.pop_frame();
+        }
+    }
+
+    /// Print the report, quoting each line from `source` when there is one
+    pub fn report(&self, source: Option<&str>) {
+        eprintln!("[profile] functions by total time");
+        eprintln!("{:>10} {:>10} {:>10}  function", "calls", "total", "self");
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(b.0)));
+        for (name, stats) in functions {
+            eprintln!(
+                "{:>10} {:>9.3}s {:>9.3}s  {}",
+                stats.calls,
+                stats.total.as_secs_f64(),
+                stats.own.as_secs_f64(),
+                name
+            );
+        }
+
+        eprintln!("[profile] lines by total time");
+        eprintln!("{:>10} {:>10}  line", "hits", "total");
+        let source_lines: Vec<&str> = source.map_or(Vec::new(), |source| source.lines().collect());
+        let mut lines: Vec<_> = self.lines.iter().collect();
+        lines.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(b.0)));
+        for (&line, stats) in lines {
+            let text = source_lines
+                .get(line as usize - 1)
+                .map_or("", |text| text.trim());
+            eprintln!(
+                "{:>10} {:>9.3}s  {:<5} {}",
+                stats.calls,
+                stats.total.as_secs_f64(),
+                line,
+                text
+            );
+        }
+    }
+
+    /// One `a;b;c microseconds` line per call stack, the input format of
+    /// `flamegraph.pl` and `inferno-flamegraph`
+    pub fn write_folded(&self, path: &Path) -> io::Result<()> {
+        let mut stacks: Vec<_> = self.folded.iter().collect();
+        stacks.sort();
+        let contents: String = stacks
+            .into_iter()
+            .map(|(stack, time)| format!("{} {}\n", stack, time.as_micros()))
+            .collect();
+        fs::write(path, contents)
+    }
+
+    fn pop_frame(&mut self) -> (Frame, Duration, Duration) {
+        let stack = self
+            .frames
+            .iter()
+            .map(|frame| frame.name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+        let frame = self.frames.pop().unwrap();
+        let elapsed = frame.start.elapsed();
+        let own = elapsed.saturating_sub(frame.children);
+        *self.folded.entry(stack).or_default() += own;
+        (frame, elapsed, own)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recursive_calls_count_total_time_once() {
+        let mut profiler = Profiler::new();
+        profiler.enter_function("f".to_string());
+        profiler.enter_function("f".to_string());
+        std::thread::sleep(Duration::from_millis(1));
+        profiler.exit_function();
+        profiler.exit_function();
+        profiler.finish();
+
+        // Counting the inner call's time again would make the total exceed
+        // the time spent in `f` at all
+        let stats = &profiler.functions["f"];
+        assert_eq!(stats.calls, 2);
+        assert_eq!(stats.total, stats.own);
+        assert!(profiler.folded.contains_key("<script>;f;f"));
+        assert!(profiler.folded.contains_key("<script>"));
+    }
+
+    #[test]
+    fn test_tail_calls_are_counted() {
+        use crate::interpreter::Interpreter;
+        use crate::parser::Parser;
+        use crate::resolver::Resolver;
+        use crate::scanner::scanner::Scanner;
+
+        let source = "fun done() { return 0; }\n\
+            fun countdown(n) {\n  if (n == 0) return done();\n  return countdown(n - 1);\n}\n\
+            countdown(100);";
+        let tokens = Scanner::new(source.to_string()).scan_tokens();
+        let statements = Parser::new(tokens).parse().ok().unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.profiler = Some(Profiler::new());
+        assert!(Resolver::new(&mut interpreter)
+            .resolve_statements(&statements)
+            .is_ok());
+        assert!(interpreter.interpret(statements).is_ok());
+        let mut profiler = interpreter.profiler.take().unwrap();
+        profiler.finish();
+
+        // Each tail call replaces the caller's frame rather than nesting in it
+        assert_eq!(profiler.functions["countdown"].calls, 101);
+        assert_eq!(profiler.functions["done"].calls, 1);
+        assert!(profiler.folded.contains_key("<script>;done"));
+    }
+}
@@ -1,18 +1,36 @@
-use std::{cell::RefCell, collections::HashMap};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
     expr::{self, Accept as AcceptExpr, Expr},
     interpreter::Interpreter,
     lang_error::{self, LangError},
+    object::literal_type::LiteralType,
+    pattern::{MatchArm, Pattern},
     scanner::token::Token,
     stmt::{self, Accept as AcceptStmt, Stmt},
 };
 
-type Scopes = Vec<RefCell<HashMap<String, bool>>>;
+/// A local variable: whether its initializer has finished, whether it was
+/// declared with `const`, and the slot it takes in its environment
+#[derive(Clone, Copy)]
+struct Local {
+    defined: bool,
+    constant: bool,
+    slot: usize,
+}
+
+type Scopes = Vec<RefCell<HashMap<String, Local>>>;
 
 impl ScopesOps<String, bool> for Scopes {
     fn insert_to_last(&mut self, key: String, value: bool) -> Option<bool> {
-        self.last().unwrap().borrow_mut().insert(key, value)
+        let mut scope = self.last().unwrap().borrow_mut();
+        let slot = scope.len();
+        let local = Local {
+            defined: value,
+            constant: false,
+            slot,
+        };
+        scope.insert(key, local).map(|local| local.defined)
     }
 }
 
@@ -25,11 +43,16 @@ trait ScopesOps<K, V> {
     fn insert_to_last(&mut self, key: K, value: V) -> Option<V>;
 }
 
-pub struct Resolver {
-    pub interpreter: Interpreter,
+pub struct Resolver<'a> {
+    interpreter: &'a mut Interpreter,
     scopes: Scopes,
     current_function: FunctionType,
     current_class: ClassType,
+    trait_methods: HashMap<String, Vec<String>>,
+    /// Loops around the code being resolved, within the current function
+    loop_depth: usize,
+    /// Whether the current function yields, which rules out returning a value
+    in_generator: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -38,6 +61,8 @@ enum FunctionType {
     Function,
     Initializer,
     Method,
+    ClassMethod,
+    Getter,
 }
 
 #[derive(Clone, Debug)]
@@ -45,30 +70,34 @@ enum ClassType {
     None,
     Class,
     SubClass,
+    Trait,
 }
 
-impl Resolver {
-    pub fn new(interpreter: Interpreter) -> Resolver {
+impl<'a> Resolver<'a> {
+    pub fn new(interpreter: &'a mut Interpreter) -> Resolver<'a> {
         Resolver {
             interpreter,
             scopes: Vec::new(),
             current_function: FunctionType::None,
             current_class: ClassType::None,
+            trait_methods: HashMap::new(),
+            loop_depth: 0,
+            in_generator: false,
         }
     }
 
-    pub fn resolve_statements(&mut self, statements: Vec<Stmt>) -> Result<(), LangError> {
+    pub fn resolve_statements(&mut self, statements: &[Stmt]) -> Result<(), LangError> {
         for statement in statements {
             self.resolve_statement(statement)?;
         }
         Ok(())
     }
 
-    fn resolve_statement(&mut self, stmt: Stmt) -> Result<(), LangError> {
+    fn resolve_statement(&mut self, stmt: &Stmt) -> Result<(), LangError> {
         stmt.accept(self)
     }
 
-    fn resolve_expression(&mut self, mut expr: Expr) -> Result<(), LangError> {
+    fn resolve_expression(&mut self, expr: &Expr) -> Result<(), LangError> {
         expr.accept(self)
     }
 
@@ -81,76 +110,218 @@ impl Resolver {
         self.scopes.pop();
     }
 
-    fn declare(&mut self, name: Token) -> Result<(), LangError> {
+    fn declare(&mut self, name: &Token) -> Result<(), LangError> {
         if self.scopes.is_empty() {
             return Ok(());
         }
-        let scope = self.scopes.pop().unwrap();
-        let result = if scope.borrow().contains_key(&name.lexeme) {
-            report_error(
+        if self
+            .scopes
+            .insert_to_last(name.lexeme.clone(), false)
+            .is_some()
+        {
+            return report_error(
                 name.line,
                 "Already a variable with this name in this scope.".to_string(),
-            )
-        } else {
-            Ok(())
-        };
-        scope.borrow_mut().insert(name.lexeme, false);
-        self.scopes.push(scope);
-        result
+            );
+        }
+        Ok(())
     }
 
-    fn define(&mut self, name: Token) {
+    fn define(&mut self, name: &Token) {
         if self.scopes.is_empty() {
             return;
         }
-        let scope = self.scopes.pop().unwrap();
-        scope.borrow_mut().insert(name.lexeme, true);
-        self.scopes.push(scope);
+        let mut scope = self.scopes.last().unwrap().borrow_mut();
+        if let Some(local) = scope.get_mut(&name.lexeme) {
+            local.defined = true;
+        }
+    }
+
+    fn define_constant(&mut self, name: &Token) {
+        self.define(name);
+        if let Some(scope) = self.scopes.last() {
+            if let Some(local) = scope.borrow_mut().get_mut(&name.lexeme) {
+                local.constant = true;
+            }
+        }
     }
 
-    fn resolve_local_variable(&mut self, name: Token) {
-        for (i, scope) in self.scopes.iter().rev().enumerate() {
-            if scope.borrow().contains_key(&name.lexeme) {
-                self.interpreter.resolve(name.id, i);
+    /// Reject assigning to a local declared with `const`. Globals are checked
+    /// by the interpreter, since they can be declared after the assignment is
+    /// resolved.
+    fn check_assignable(&self, name: &Token) -> Result<(), LangError> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(local) = scope.borrow().get(&name.lexeme) {
+                if local.constant {
+                    let message = format!("Can't assign to constant '{}'.", name.lexeme);
+                    return report_error(name.line, message);
+                }
+                return Ok(());
             }
         }
+        Ok(())
+    }
+
+    /// Record where the innermost declaration of a local lives; anything not
+    /// found is left to be looked up as a global
+    fn resolve_local_variable(&mut self, expr_id: u64, name: &Token) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(local) = scope.borrow().get(&name.lexeme) {
+                self.interpreter.resolve(expr_id, depth, local.slot);
+                return;
+            }
+        }
+    }
+
+    /// Resolve the classes a pattern matches against. The interpreter looks
+    /// them up before the arm's scope exists, so this runs outside it.
+    fn resolve_pattern_classes(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Instance {
+                class,
+                positional,
+                named,
+            } => {
+                self.resolve_local_variable(class.id, &class.name);
+                for pattern in positional.iter().chain(named.iter().map(|(_, p)| p)) {
+                    self.resolve_pattern_classes(pattern);
+                }
+            }
+            Pattern::Alternatives(alternatives) => {
+                for alternative in alternatives {
+                    self.resolve_pattern_classes(alternative);
+                }
+            }
+            Pattern::Binding(_) | Pattern::Wildcard | Pattern::Literal(_) => (),
+        }
+    }
+
+    /// Declare the names a pattern binds in the arm's scope, in the order the
+    /// interpreter defines them
+    fn resolve_pattern(&mut self, pattern: &Pattern) -> Result<(), LangError> {
+        match pattern {
+            Pattern::Instance {
+                positional, named, ..
+            } => {
+                for pattern in positional.iter().chain(named.iter().map(|(_, p)| p)) {
+                    self.resolve_pattern(pattern)?;
+                }
+            }
+            Pattern::Alternatives(_) => {
+                if let Some(name) = pattern.bindings().first() {
+                    return report_error(
+                        name.line,
+                        "Alternatives in a pattern can't bind names.".to_string(),
+                    );
+                }
+            }
+            Pattern::Binding(name) => {
+                self.declare(name)?;
+                self.define(name);
+            }
+            Pattern::Wildcard | Pattern::Literal(_) => (),
+        }
+        Ok(())
+    }
+
+    /// Two traits providing the same method is an error unless the class
+    /// overrides it, and so is listing a trait twice
+    fn check_trait_conflicts(&self, class: &stmt::Class) -> Result<(), LangError> {
+        let mut providers: HashMap<&str, &str> = HashMap::new();
+        for (index, lox_trait) in class.traits.iter().enumerate() {
+            let trait_name = lox_trait.name.lexeme.as_str();
+            let earlier = &class.traits[..index];
+            if earlier.iter().any(|other| other.name.lexeme == trait_name) {
+                let message = format!("Trait '{}' is listed more than once.", trait_name);
+                return report_error(lox_trait.name.line, message);
+            }
+            let methods = match self.trait_methods.get(trait_name) {
+                Some(methods) => methods,
+                None => continue,
+            };
+            for method in methods {
+                if class.methods.iter().any(|m| m.name.lexeme == *method) {
+                    continue;
+                }
+                if let Some(other) = providers.insert(method, trait_name) {
+                    let message = format!(
+                        "Method '{}' is defined by both '{}' and '{}'.",
+                        method, other, trait_name
+                    );
+                    return report_error(lox_trait.name.line, message);
+                }
+            }
+        }
+        Ok(())
     }
 
     fn resolve_function(
         &mut self,
-        function: stmt::Function,
+        function: &stmt::Function,
         function_type: FunctionType,
     ) -> Result<(), LangError> {
         let enclosing_function = self.current_function.clone();
         self.current_function = function_type;
+        let enclosing_loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let enclosing_generator = std::mem::replace(&mut self.in_generator, function.generator);
         self.begin_scope();
-        for param in function.params {
-            self.declare(param.clone())?;
-            self.define(param.clone());
+        for (param, default) in function.params.iter().zip(&function.defaults) {
+            // A default can refer to the parameters before it
+            if let Some(default) = default {
+                self.resolve_expression(default)?;
+            }
+            self.declare(param)?;
+            self.define(param);
+        }
+        if let Some(rest) = &function.rest {
+            self.declare(rest)?;
+            self.define(rest);
         }
-        self.resolve_statements(function.body)?;
+        self.resolve_statements(&function.body)?;
         self.end_scope();
 
         self.current_function = enclosing_function;
+        self.loop_depth = enclosing_loop_depth;
+        self.in_generator = enclosing_generator;
         Ok(())
     }
 }
 
-impl stmt::Visitor<Result<(), LangError>> for Resolver {
+impl stmt::Visitor<Result<(), LangError>> for Resolver<'_> {
     fn visit_block_stmt(&mut self, stmt: &stmt::Block) -> Result<(), LangError> {
         self.begin_scope();
-        self.resolve_statements(stmt.clone().statements)?;
+        self.resolve_statements(&stmt.statements)?;
         self.end_scope();
         Ok(())
     }
 
+    fn visit_break_stmt(&mut self, stmt: &stmt::Break) -> Result<(), LangError> {
+        if self.loop_depth == 0 {
+            return report_error(
+                stmt.keyword.line,
+                "Can't use 'break' outside of a loop.".to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    fn visit_continue_stmt(&mut self, stmt: &stmt::Continue) -> Result<(), LangError> {
+        if self.loop_depth == 0 {
+            return report_error(
+                stmt.keyword.line,
+                "Can't use 'continue' outside of a loop.".to_string(),
+            );
+        }
+        Ok(())
+    }
+
     fn visit_class_stmt(&mut self, stmt: &stmt::Class) -> Result<(), LangError> {
         let enclosing_class = self.current_class.clone();
         self.current_class = ClassType::Class;
-        self.declare(stmt.name.clone())?;
-        self.define(stmt.name.clone());
+        self.declare(&stmt.name)?;
+        self.define(&stmt.name);
 
-        if let Some(superclass) = stmt.superclass.clone() {
+        if let Some(superclass) = &stmt.superclass {
             if stmt.name.lexeme == superclass.name.lexeme {
                 report_error(
                     superclass.name.line,
@@ -158,22 +329,32 @@ impl stmt::Visitor<Result<(), LangError>> for Resolver {
                 )?;
             }
             self.current_class = ClassType::SubClass;
-            self.resolve_expression(Expr::Variable(superclass))?;
+            self.resolve_local_variable(superclass.id, &superclass.name);
             self.begin_scope();
             self.scopes.insert_to_last("super".to_string(), true);
         }
+        for lox_trait in stmt.traits.iter() {
+            self.resolve_local_variable(lox_trait.id, &lox_trait.name);
+        }
+        self.check_trait_conflicts(stmt)?;
         self.begin_scope();
         self.scopes.insert_to_last("this".to_string(), true);
         for method in stmt.methods.iter() {
-            let declaration = if method.name.lexeme == "init".to_string() {
+            let declaration = if method.name.lexeme == "init" {
                 FunctionType::Initializer
             } else {
                 FunctionType::Method
             };
-            self.resolve_function(method.clone(), declaration)?;
+            self.resolve_function(method, declaration)?;
+        }
+        for method in stmt.class_methods.iter() {
+            self.resolve_function(method, FunctionType::ClassMethod)?;
+        }
+        for getter in stmt.getters.iter() {
+            self.resolve_function(getter, FunctionType::Getter)?;
         }
         self.end_scope();
-        if let Some(_) = stmt.superclass.clone() {
+        if stmt.superclass.is_some() {
             self.end_scope();
         }
 
@@ -182,29 +363,64 @@ impl stmt::Visitor<Result<(), LangError>> for Resolver {
     }
 
     fn visit_expression_stmt(&mut self, stmt: &stmt::Expression) -> Result<(), LangError> {
-        self.resolve_expression(stmt.clone().expression)
+        self.resolve_expression(&stmt.expression)
     }
 
-    fn visit_function_stmt(&mut self, stmt: &stmt::Function) -> Result<(), LangError> {
-        let cloned_stmt = stmt.clone();
-        self.declare(cloned_stmt.name.clone())?;
-        self.define(cloned_stmt.name.clone());
+    fn visit_function_stmt(&mut self, stmt: &Rc<stmt::Function>) -> Result<(), LangError> {
+        self.declare(&stmt.name)?;
+        self.define(&stmt.name);
 
-        self.resolve_function(cloned_stmt, FunctionType::Function)
+        self.resolve_function(stmt, FunctionType::Function)
     }
 
     fn visit_if_stmt(&mut self, stmt: &stmt::If) -> Result<(), LangError> {
-        let cloned_stmt = stmt.clone();
-        self.resolve_expression(cloned_stmt.condition)?;
-        self.resolve_statement(*cloned_stmt.then_statement)?;
-        if stmt.else_statement.is_none() {
-            return Ok(());
+        self.resolve_expression(&stmt.condition)?;
+        self.resolve_statement(&stmt.then_statement)?;
+        match &stmt.else_statement {
+            Some(else_statement) => self.resolve_statement(else_statement),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_match_stmt(&mut self, stmt: &stmt::Match) -> Result<(), LangError> {
+        self.resolve_expression(&stmt.value)?;
+        let last_is_default = stmt
+            .arms
+            .last()
+            .is_some_and(|arm| arm.guard.is_none() && arm.pattern.is_irrefutable());
+        if !last_is_default {
+            return report_error(
+                stmt.keyword.line,
+                "Match must end with a '_' arm.".to_string(),
+            );
+        }
+        for arm in stmt.arms.iter() {
+            self.resolve_pattern_classes(&arm.pattern);
+            self.begin_scope();
+            self.resolve_pattern(&arm.pattern)?;
+            if let Some(guard) = &arm.guard {
+                self.resolve_expression(guard)?;
+            }
+            self.resolve_statement(&arm.body)?;
+            self.end_scope();
         }
-        self.resolve_statement(*stmt.clone().else_statement.unwrap())
+        warn_unreachable_arms(&stmt.arms);
+        Ok(())
+    }
+
+    fn visit_multiassign_stmt(&mut self, stmt: &stmt::MultiAssign) -> Result<(), LangError> {
+        self.resolve_expression(&stmt.value)?;
+        for target in stmt.targets.iter() {
+            if let Expr::Variable(variable) = target {
+                self.check_assignable(&variable.name)?;
+            }
+            self.resolve_expression(target)?;
+        }
+        Ok(())
     }
 
     fn visit_print_stmt(&mut self, stmt: &stmt::Print) -> Result<(), LangError> {
-        self.resolve_expression(stmt.clone().expression)
+        self.resolve_expression(&stmt.expression)
     }
 
     fn visit_return_stmt(&mut self, stmt: &stmt::Return) -> Result<(), LangError> {
@@ -217,56 +433,136 @@ impl stmt::Visitor<Result<(), LangError>> for Resolver {
                 stmt.keyword.line,
                 "Can't return a value from an initializer.".to_string(),
             ),
+            _ if self.in_generator && !is_nil_literal(&stmt.value) => report_error(
+                stmt.keyword.line,
+                "Can't return a value from a generator.".to_string(),
+            ),
             _ => Ok(()),
         };
-        self.resolve_expression(stmt.clone().value)?;
+        self.resolve_expression(&stmt.value)?;
         result
     }
 
+    fn visit_trait_stmt(&mut self, stmt: &stmt::Trait) -> Result<(), LangError> {
+        let enclosing_class = self.current_class.clone();
+        self.current_class = ClassType::Trait;
+        self.declare(&stmt.name)?;
+        self.define(&stmt.name);
+
+        self.begin_scope();
+        self.scopes.insert_to_last("this".to_string(), true);
+        for method in stmt.methods.iter() {
+            self.resolve_function(method, FunctionType::Method)?;
+        }
+        self.end_scope();
+        let method_names = stmt.methods.iter().map(|m| m.name.lexeme.clone());
+        self.trait_methods
+            .insert(stmt.name.lexeme.clone(), method_names.collect());
+
+        self.current_class = enclosing_class;
+        Ok(())
+    }
+
     fn visit_var_stmt(&mut self, stmt: &stmt::Var) -> Result<(), LangError> {
-        let cloned_stmt = stmt.clone();
-        self.declare(cloned_stmt.name.clone())?;
-        self.resolve_expression(cloned_stmt.initializer)?;
-        self.define(cloned_stmt.name);
+        for name in stmt.names.iter() {
+            self.declare(name)?;
+        }
+        self.resolve_expression(&stmt.initializer)?;
+        for name in stmt.names.iter() {
+            if stmt.constant {
+                self.define_constant(name);
+            } else {
+                self.define(name);
+            }
+        }
         Ok(())
     }
 
+    fn visit_forin_stmt(&mut self, stmt: &stmt::ForIn) -> Result<(), LangError> {
+        self.resolve_expression(&stmt.iterable)?;
+        // Each iteration gets its own scope, so closures keep their element
+        self.begin_scope();
+        self.declare(&stmt.name)?;
+        self.define(&stmt.name);
+        let enclosing_loop_depth = self.loop_depth;
+        self.loop_depth += 1;
+        let result = self.resolve_statement(&stmt.body);
+        self.loop_depth = enclosing_loop_depth;
+        self.end_scope();
+        result
+    }
+
+    fn visit_yield_stmt(&mut self, stmt: &stmt::Yield) -> Result<(), LangError> {
+        let result = match self.current_function {
+            FunctionType::None => report_error(
+                stmt.keyword.line,
+                "Can't yield from top-level code.".to_string(),
+            ),
+            FunctionType::Initializer => report_error(
+                stmt.keyword.line,
+                "Can't yield from an initializer.".to_string(),
+            ),
+            _ => Ok(()),
+        };
+        self.resolve_expression(&stmt.value)?;
+        result
+    }
+
     fn visit_while_stmt(&mut self, stmt: &stmt::While) -> Result<(), LangError> {
-        let cloned_stmt = stmt.clone();
-        self.resolve_expression(cloned_stmt.condition)?;
-        self.resolve_statement(*cloned_stmt.body)
+        self.resolve_expression(&stmt.condition)?;
+        if let Some(increment) = &stmt.increment {
+            self.resolve_expression(increment)?;
+        }
+        let enclosing_loop_depth = self.loop_depth;
+        self.loop_depth += 1;
+        let result = self.resolve_statement(&stmt.body);
+        self.loop_depth = enclosing_loop_depth;
+        result
     }
 }
 
-impl expr::Visitor<Result<(), LangError>> for Resolver {
+impl expr::Visitor<Result<(), LangError>> for Resolver<'_> {
     fn visit_assign_expr(&mut self, expr: &expr::Assign) -> Result<(), LangError> {
-        let cloned_expr = expr.clone();
-        self.resolve_expression(*cloned_expr.value)?;
-        self.resolve_local_variable(cloned_expr.name);
+        self.resolve_expression(&expr.value)?;
+        self.check_assignable(&expr.name)?;
+        self.resolve_local_variable(expr.id, &expr.name);
         Ok(())
     }
 
     fn visit_binary_expr(&mut self, expr: &expr::Binary) -> Result<(), LangError> {
-        let cloned_expr = expr.clone();
-        self.resolve_expression(*cloned_expr.left)?;
-        self.resolve_expression(*cloned_expr.right)
+        self.resolve_expression(&expr.left)?;
+        self.resolve_expression(&expr.right)
     }
 
     fn visit_call_expr(&mut self, expr: &expr::Call) -> Result<(), LangError> {
-        let cloned_expr = expr.clone();
-        self.resolve_expression(*cloned_expr.callee)?;
-        for arg in cloned_expr.arguments {
+        self.resolve_expression(&expr.callee)?;
+        for arg in expr.arguments.iter() {
             self.resolve_expression(arg)?;
         }
         Ok(())
     }
 
+    fn visit_conditional_expr(&mut self, expr: &expr::Conditional) -> Result<(), LangError> {
+        self.resolve_expression(&expr.condition)?;
+        self.resolve_expression(&expr.then_branch)?;
+        self.resolve_expression(&expr.else_branch)
+    }
+
     fn visit_get_expr(&mut self, expr: &expr::Get) -> Result<(), LangError> {
-        self.resolve_expression(*expr.object.clone())
+        self.resolve_expression(&expr.object)
     }
 
     fn visit_grouping_expr(&mut self, expr: &expr::Grouping) -> Result<(), LangError> {
-        self.resolve_expression(*expr.clone().expression)
+        self.resolve_expression(&expr.expression)
+    }
+
+    fn visit_index_expr(&mut self, expr: &expr::Index) -> Result<(), LangError> {
+        self.resolve_expression(&expr.object)?;
+        self.resolve_expression(&expr.index)
+    }
+
+    fn visit_lambda_expr(&mut self, expr: &expr::Lambda) -> Result<(), LangError> {
+        self.resolve_function(&expr.function, FunctionType::Function)
     }
 
     fn visit_literal_expr(&mut self, _expr: &expr::Literal) -> Result<(), LangError> {
@@ -274,13 +570,18 @@ impl expr::Visitor<Result<(), LangError>> for Resolver {
     }
 
     fn visit_logical_expr(&mut self, expr: &expr::Logical) -> Result<(), LangError> {
-        self.resolve_expression(*expr.clone().left)?;
-        self.resolve_expression(*expr.clone().right)
+        self.resolve_expression(&expr.left)?;
+        self.resolve_expression(&expr.right)
+    }
+
+    fn visit_range_expr(&mut self, expr: &expr::Range) -> Result<(), LangError> {
+        self.resolve_expression(&expr.start)?;
+        self.resolve_expression(&expr.end)
     }
 
     fn visit_set_expr(&mut self, expr: &expr::Set) -> Result<(), LangError> {
-        self.resolve_expression(*expr.clone().value)?;
-        self.resolve_expression(*expr.clone().object)
+        self.resolve_expression(&expr.value)?;
+        self.resolve_expression(&expr.object)
     }
 
     fn visit_super_expr(&mut self, expr: &expr::Super) -> Result<(), LangError> {
@@ -296,7 +597,13 @@ impl expr::Visitor<Result<(), LangError>> for Resolver {
                 "Can't use 'super' in a class with no superclass.".to_string(),
             );
         }
-        self.resolve_local_variable(expr.clone().keyword);
+        if let ClassType::Trait = self.current_class {
+            return report_error(
+                expr.keyword.line,
+                "Can't use 'super' in a trait.".to_string(),
+            );
+        }
+        self.resolve_local_variable(expr.id, &expr.keyword);
         Ok(())
     }
 
@@ -307,19 +614,26 @@ impl expr::Visitor<Result<(), LangError>> for Resolver {
                 "Can't use 'this' outside of a class.".to_string(),
             );
         }
-        self.resolve_local_variable(expr.keyword.clone());
+        self.resolve_local_variable(expr.id, &expr.keyword);
+        Ok(())
+    }
+
+    fn visit_tuple_expr(&mut self, expr: &expr::Tuple) -> Result<(), LangError> {
+        for element in expr.elements.iter() {
+            self.resolve_expression(element)?;
+        }
         Ok(())
     }
 
     fn visit_unary_expr(&mut self, expr: &expr::Unary) -> Result<(), LangError> {
-        self.resolve_expression(*expr.clone().right)
+        self.resolve_expression(&expr.right)
     }
 
     fn visit_variable_expr(&mut self, expr: &expr::Variable) -> Result<(), LangError> {
         let result = if !self.scopes.is_empty() {
             let scope = self.scopes.last().unwrap().borrow();
             let variable = scope.get(&expr.name.lexeme);
-            if variable.is_some() && !variable.unwrap() {
+            if variable.is_some_and(|local| !local.defined) {
                 return report_error(
                     expr.name.line,
                     "Can't read local variable in its own initializer.".to_string(),
@@ -330,12 +644,38 @@ impl expr::Visitor<Result<(), LangError>> for Resolver {
             Ok(())
         };
 
-        self.resolve_local_variable(expr.clone().name);
+        self.resolve_local_variable(expr.id, &expr.name);
         result
     }
 }
 
+/// Warn about arms that an earlier arm always takes the value from
+fn warn_unreachable_arms(arms: &[MatchArm]) {
+    let mut covered: Vec<&LiteralType> = Vec::new();
+    let mut exhausted = false;
+    for arm in arms {
+        let literals = arm.pattern.literals();
+        let unreachable = exhausted
+            || literals
+                .as_ref()
+                .is_some_and(|literals| literals.iter().all(|literal| covered.contains(literal)));
+        if unreachable {
+            lang_error::warning(arm.arrow.line, "Unreachable match arm.".to_string());
+        }
+        if arm.guard.is_some() {
+            continue;
+        }
+        exhausted |= arm.pattern.is_irrefutable();
+        covered.extend(literals.unwrap_or_default());
+    }
+}
+
+/// Whether a `return` has no value, since the parser fills in `nil` for one
+fn is_nil_literal(expr: &Expr) -> bool {
+    matches!(expr, Expr::Literal(literal) if matches!(literal.value, LiteralType::Nil))
+}
+
 fn report_error(line: u32, message: String) -> Result<(), LangError> {
     lang_error::error(line, message);
-    Err(LangError::ParseError)
+    Err(LangError::ResolveError)
 }
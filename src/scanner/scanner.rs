@@ -11,17 +11,22 @@ pub struct Scanner {
     start: usize,
     current: usize,
     line: u32,
-    token_id_counter: u64,
 }
 
 const AND: &str = "and";
+const BREAK: &str = "break";
 const CLASS: &str = "class";
+const CONST: &str = "const";
+const CONTINUE: &str = "continue";
 const ELSE: &str = "else";
 const FALSE: &str = "false";
 const FOR: &str = "for";
 const FUN: &str = "fun";
 const IF: &str = "if";
+const IN: &str = "in";
 const INF: &str = "inf";
+const IS: &str = "is";
+const MATCH: &str = "match";
 const NAN: &str = "NaN";
 const NIL: &str = "nil";
 const OR: &str = "or";
@@ -29,9 +34,12 @@ const PRINT: &str = "print";
 const RETURN: &str = "return";
 const SUPER: &str = "super";
 const THIS: &str = "this";
+const TRAIT: &str = "trait";
 const TRUE: &str = "true";
 const VAR: &str = "var";
 const WHILE: &str = "while";
+const WITH: &str = "with";
+const YIELD: &str = "yield";
 
 impl Default for Scanner {
     fn default() -> Self {
@@ -41,7 +49,6 @@ impl Default for Scanner {
             start: 0,
             current: 0,
             line: 1,
-            token_id_counter: 1,
         }
     }
 }
@@ -59,18 +66,11 @@ impl Scanner {
             self.start = self.current;
             self.scan_token();
         }
-        let token_id = self.allocate_token_id();
-        let eof_token = Token::new(TokenType::EOF, String::from(""), None, self.line, token_id);
+        let eof_token = Token::new(TokenType::EOF, String::from(""), None, self.line);
         self.tokens.push(eof_token);
         self.tokens.clone()
     }
 
-    fn allocate_token_id(&mut self) -> u64 {
-        let token_id = self.token_id_counter;
-        self.token_id_counter += 1;
-        token_id
-    }
-
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
@@ -82,12 +82,73 @@ impl Scanner {
             ')' => self.add_token_without_value(TokenType::RightParen),
             '{' => self.add_token_without_value(TokenType::LeftBrace),
             '}' => self.add_token_without_value(TokenType::RightBrace),
+            '[' => self.add_token_without_value(TokenType::LeftBracket),
+            ']' => self.add_token_without_value(TokenType::RightBracket),
+            ':' => self.add_token_without_value(TokenType::Colon),
             ',' => self.add_token_without_value(TokenType::Comma),
-            '.' => self.add_token_without_value(TokenType::Dot),
-            '-' => self.add_token_without_value(TokenType::Minus),
-            '+' => self.add_token_without_value(TokenType::Plus),
+            '.' => {
+                if self.peek() == '.' && self.next_peek() == '.' {
+                    self.current += 2;
+                    self.add_token_without_value(TokenType::DotDotDot);
+                } else if self.match_char('.') {
+                    self.add_token_without_value(TokenType::DotDot);
+                } else {
+                    self.add_token_without_value(TokenType::Dot);
+                }
+            }
+            '-' => {
+                let token_type = if self.match_char('-') {
+                    TokenType::MinusMinus
+                } else if self.match_char('=') {
+                    TokenType::MinusEqual
+                } else {
+                    TokenType::Minus
+                };
+                self.add_token_without_value(token_type)
+            }
+            '+' => {
+                let token_type = if self.match_char('+') {
+                    TokenType::PlusPlus
+                } else if self.match_char('=') {
+                    TokenType::PlusEqual
+                } else {
+                    TokenType::Plus
+                };
+                self.add_token_without_value(token_type)
+            }
             ';' => self.add_token_without_value(TokenType::Semicolon),
-            '*' => self.add_token_without_value(TokenType::Star),
+            '*' => {
+                let token_type = if self.match_char('*') {
+                    TokenType::StarStar
+                } else if self.match_char('=') {
+                    TokenType::StarEqual
+                } else {
+                    TokenType::Star
+                };
+                self.add_token_without_value(token_type)
+            }
+            '%' => {
+                let token_type = if self.match_char('=') {
+                    TokenType::PercentEqual
+                } else {
+                    TokenType::Percent
+                };
+                self.add_token_without_value(token_type)
+            }
+            '?' => {
+                let token_type = if self.match_char('?') {
+                    TokenType::QuestionQuestion
+                } else if self.match_char('.') {
+                    TokenType::QuestionDot
+                } else {
+                    TokenType::Question
+                };
+                self.add_token_without_value(token_type)
+            }
+            '&' => self.add_token_without_value(TokenType::Ampersand),
+            '|' => self.add_token_without_value(TokenType::Pipe),
+            '^' => self.add_token_without_value(TokenType::Caret),
+            '~' => self.add_token_without_value(TokenType::Tilde),
             ' ' | '\r' | '\t' => (),
             '"' => self.string(),
             '\n' => {
@@ -104,6 +165,8 @@ impl Scanner {
             '=' => {
                 let token_type = if self.match_char('=') {
                     TokenType::EqualEqual
+                } else if self.match_char('>') {
+                    TokenType::Arrow
                 } else {
                     TokenType::Equal
                 };
@@ -112,6 +175,8 @@ impl Scanner {
             '<' => {
                 let token_type = if self.match_char('=') {
                     TokenType::LessEqual
+                } else if self.match_char('<') {
+                    TokenType::LessLess
                 } else {
                     TokenType::Less
                 };
@@ -120,6 +185,8 @@ impl Scanner {
             '>' => {
                 let token_type = if self.match_char('=') {
                     TokenType::GreaterEqual
+                } else if self.match_char('>') {
+                    TokenType::GreaterGreater
                 } else {
                     TokenType::Greater
                 };
@@ -130,6 +197,8 @@ impl Scanner {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                } else if self.match_char('=') {
+                    self.add_token_without_value(TokenType::SlashEqual);
                 } else {
                     self.add_token_without_value(TokenType::Slash);
                 }
@@ -173,8 +242,7 @@ impl Scanner {
 
     fn add_token(&mut self, token_type: TokenType, literal: Option<LiteralType>) {
         let lexeme = self.source.substring(self.start, self.current).to_string();
-        let token_id = self.allocate_token_id();
-        let token = Token::new(token_type, lexeme, literal, self.line, token_id);
+        let token = Token::new(token_type, lexeme, literal, self.line);
         self.tokens.push(token);
     }
 
@@ -214,7 +282,7 @@ impl Scanner {
     }
 
     fn is_digit(&self, c: char) -> bool {
-        c >= '0' && c <= '9'
+        c.is_ascii_digit()
     }
 
     fn number(&mut self) {
@@ -222,9 +290,16 @@ impl Scanner {
             self.advance();
         }
 
-        if self.peek() == '.' && self.is_digit(self.next_peek()) {
-            self.advance();
+        // Only a fractional part makes a float
+        if self.peek() != '.' || !self.is_digit(self.next_peek()) {
+            let text = self.source.substring(self.start, self.current);
+            // The parser reports an integer too large for i64, unless it is
+            // negated to i64::MIN
+            let literal = text.parse::<i64>().ok().map(LiteralType::Int);
+            self.add_token(TokenType::Number, literal);
+            return;
         }
+        self.advance();
 
         while self.is_digit(self.peek()) {
             self.advance();
@@ -244,7 +319,7 @@ impl Scanner {
     }
 
     fn is_alpha(&self, c: char) -> bool {
-        (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+        c.is_ascii_alphabetic() || c == '_'
     }
 
     fn is_alpha_numeric(&self, c: char) -> bool {
@@ -264,21 +339,30 @@ impl Scanner {
         }
         let token_type = match text {
             AND => TokenType::And,
+            BREAK => TokenType::Break,
             CLASS => TokenType::Class,
+            CONST => TokenType::Const,
+            CONTINUE => TokenType::Continue,
             ELSE => TokenType::Else,
             FALSE => TokenType::False,
             FOR => TokenType::For,
             FUN => TokenType::Fun,
             IF => TokenType::If,
+            IN => TokenType::In,
+            IS => TokenType::Is,
+            MATCH => TokenType::Match,
             NIL => TokenType::Nil,
             OR => TokenType::Or,
             PRINT => TokenType::Print,
             RETURN => TokenType::Return,
             SUPER => TokenType::Super,
             THIS => TokenType::This,
+            TRAIT => TokenType::Trait,
             TRUE => TokenType::True,
             VAR => TokenType::Var,
             WHILE => TokenType::While,
+            WITH => TokenType::With,
+            YIELD => TokenType::Yield,
             _ => TokenType::Identifier,
         };
         self.add_token_without_value(token_type);
@@ -310,12 +394,73 @@ mod tests {
     }
 
     #[test]
-    fn test_allocate_token_id() {
-        let mut scanner = create_scanner();
-        let token_id_1 = scanner.allocate_token_id();
-        assert_eq!(token_id_1, 1);
-        let token_id_2 = scanner.allocate_token_id();
-        assert_eq!(token_id_2, 2);
+    fn test_scan_operators() {
+        let mut scanner1 = Scanner::new(String::from("a += b++ ** 2 /= c // d"));
+        let token_types: Vec<TokenType> = scanner1
+            .scan_tokens()
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect();
+        assert_eq!(
+            token_types,
+            vec![
+                TokenType::Identifier,
+                TokenType::PlusEqual,
+                TokenType::Identifier,
+                TokenType::PlusPlus,
+                TokenType::StarStar,
+                TokenType::Number,
+                TokenType::SlashEqual,
+                TokenType::Identifier,
+                TokenType::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_question_operators() {
+        let mut scanner1 = Scanner::new(String::from("a ? b?.c : d ?? e"));
+        let token_types: Vec<TokenType> = scanner1
+            .scan_tokens()
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect();
+        assert_eq!(
+            token_types,
+            vec![
+                TokenType::Identifier,
+                TokenType::Question,
+                TokenType::Identifier,
+                TokenType::QuestionDot,
+                TokenType::Identifier,
+                TokenType::Colon,
+                TokenType::Identifier,
+                TokenType::QuestionQuestion,
+                TokenType::Identifier,
+                TokenType::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_range() {
+        let mut scanner1 = Scanner::new(String::from("x in 0..10"));
+        let token_types: Vec<TokenType> = scanner1
+            .scan_tokens()
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect();
+        assert_eq!(
+            token_types,
+            vec![
+                TokenType::Identifier,
+                TokenType::In,
+                TokenType::Number,
+                TokenType::DotDot,
+                TokenType::Number,
+                TokenType::EOF,
+            ]
+        );
     }
 
     #[test]
@@ -423,10 +568,21 @@ mod tests {
         scanner1.current = 1;
         scanner1.number();
         assert_eq!(scanner1.tokens[0].token_type, TokenType::Number);
-        assert_eq!(
-            *scanner1.tokens[0].literal.as_ref().unwrap(),
-            LiteralType::Num(123.0)
-        );
+        assert!(matches!(
+            scanner1.tokens[0].literal,
+            Some(LiteralType::Int(123))
+        ));
+    }
+
+    #[test]
+    fn test_number_integer_too_large() {
+        let mut scanner1 = create_scanner();
+        scanner1.source = String::from("9223372036854775808");
+        scanner1.current = 1;
+        scanner1.number();
+        // Left for the parser, which allows it only as -9223372036854775808
+        assert_eq!(scanner1.tokens[0].token_type, TokenType::Number);
+        assert!(scanner1.tokens[0].literal.is_none());
     }
 
     #[test]
@@ -436,10 +592,10 @@ mod tests {
         scanner1.current = 1;
         scanner1.number();
         assert_eq!(scanner1.tokens[0].token_type, TokenType::Number);
-        assert_eq!(
-            *scanner1.tokens[0].literal.as_ref().unwrap(),
-            LiteralType::Num(123.456)
-        );
+        assert!(matches!(
+            scanner1.tokens[0].literal,
+            Some(LiteralType::Num(n)) if n == 123.456
+        ));
     }
 
     #[test]
@@ -457,4 +613,13 @@ mod tests {
         scanner1.identifier();
         assert_eq!(scanner1.tokens[0].token_type, TokenType::And);
     }
+
+    #[test]
+    fn test_arrow() {
+        let mut scanner1 = Scanner::new(String::from("= == =>"));
+        scanner1.scan_tokens();
+        assert_eq!(scanner1.tokens[0].token_type, TokenType::Equal);
+        assert_eq!(scanner1.tokens[1].token_type, TokenType::EqualEqual);
+        assert_eq!(scanner1.tokens[2].token_type, TokenType::Arrow);
+    }
 }
@@ -6,7 +6,6 @@ pub struct Token {
     pub lexeme: String,
     pub literal: Option<LiteralType>,
     pub line: u32,
-    pub id: u64,
 }
 
 impl Token {
@@ -15,14 +14,12 @@ impl Token {
         lexeme: String,
         literal: Option<LiteralType>,
         line: u32,
-        id: u64,
     ) -> Token {
         Token {
             token_type,
             lexeme,
             literal,
             line,
-            id,
         }
     }
 }
@@ -34,16 +31,28 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
+    Percent,
     Plus,
+    Question,
     Semicolon,
     Slash,
     Star,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
 
     // One or two character tokens.
+    Arrow,
     Bang,
+    DotDot,
+    DotDotDot,
     BangEqual,
     Equal,
     EqualEqual,
@@ -51,6 +60,18 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    MinusMinus,
+    MinusEqual,
+    PercentEqual,
+    PlusEqual,
+    PlusPlus,
+    QuestionDot,
+    QuestionQuestion,
+    SlashEqual,
+    StarEqual,
+    StarStar,
 
     // Literals.
     Identifier,
@@ -59,21 +80,31 @@ pub enum TokenType {
 
     // Keywords.
     And,
+    Break,
     Class,
+    Const,
+    Continue,
     Else,
     False,
     Fun,
     For,
     If,
+    In,
+    Is,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Trait,
     True,
     Var,
     While,
+    With,
+    Yield,
 
+    #[allow(clippy::upper_case_acronyms)]
     EOF,
 }
@@ -1,16 +1,26 @@
+use std::rc::Rc;
+
 use crate::expr::{Expr, Variable};
+use crate::pattern::{MatchArm, VarPattern};
 use crate::scanner::token::Token;
 
 pub trait Visitor<T> {
     fn visit_block_stmt(&mut self, stmt: &Block) -> T;
+    fn visit_break_stmt(&mut self, stmt: &Break) -> T;
     fn visit_class_stmt(&mut self, stmt: &Class) -> T;
+    fn visit_continue_stmt(&mut self, stmt: &Continue) -> T;
     fn visit_expression_stmt(&mut self, stmt: &Expression) -> T;
-    fn visit_function_stmt(&mut self, stmt: &Function) -> T;
+    fn visit_forin_stmt(&mut self, stmt: &ForIn) -> T;
+    fn visit_function_stmt(&mut self, stmt: &Rc<Function>) -> T;
     fn visit_if_stmt(&mut self, stmt: &If) -> T;
+    fn visit_match_stmt(&mut self, stmt: &Match) -> T;
+    fn visit_multiassign_stmt(&mut self, stmt: &MultiAssign) -> T;
     fn visit_print_stmt(&mut self, stmt: &Print) -> T;
     fn visit_return_stmt(&mut self, stmt: &Return) -> T;
+    fn visit_trait_stmt(&mut self, stmt: &Trait) -> T;
     fn visit_var_stmt(&mut self, stmt: &Var) -> T;
     fn visit_while_stmt(&mut self, stmt: &While) -> T;
+    fn visit_yield_stmt(&mut self, stmt: &Yield) -> T;
 }
 
 pub trait Accept<T> {
@@ -20,28 +30,42 @@ pub trait Accept<T> {
 #[derive(Clone, Debug)]
 pub enum Stmt {
     Block(Block),
+    Break(Break),
     Class(Class),
+    Continue(Continue),
     Expression(Expression),
-    Function(Function),
+    ForIn(ForIn),
+    Function(Rc<Function>),
     If(Box<If>),
+    Match(Match),
+    MultiAssign(MultiAssign),
     Print(Print),
     Return(Return),
+    Trait(Trait),
     Var(Var),
     While(Box<While>),
+    Yield(Yield),
 }
 
 impl<T> Accept<T> for Stmt {
     fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         match self {
             Stmt::Block(e) => e.accept(visitor),
+            Stmt::Break(e) => e.accept(visitor),
             Stmt::Class(e) => e.accept(visitor),
+            Stmt::Continue(e) => e.accept(visitor),
             Stmt::Expression(e) => e.accept(visitor),
+            Stmt::ForIn(e) => e.accept(visitor),
             Stmt::Function(e) => e.accept(visitor),
             Stmt::If(e) => e.accept(visitor),
+            Stmt::Match(e) => e.accept(visitor),
+            Stmt::MultiAssign(e) => e.accept(visitor),
             Stmt::Print(e) => e.accept(visitor),
             Stmt::Return(e) => e.accept(visitor),
+            Stmt::Trait(e) => e.accept(visitor),
             Stmt::Var(e) => e.accept(visitor),
             Stmt::While(e) => e.accept(visitor),
+            Stmt::Yield(e) => e.accept(visitor),
         }
     }
 }
@@ -63,19 +87,49 @@ impl<T> Accept<T> for Block {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Break {
+    pub keyword: Token,
+}
+
+impl Break {
+    pub fn new(keyword: Token) -> Break {
+        Break { keyword }
+    }
+}
+
+impl<T> Accept<T> for Break {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_break_stmt(self)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Class {
     pub name: Token,
     pub superclass: Option<Variable>,
-    pub methods: Vec<Function>,
+    pub traits: Vec<Variable>,
+    pub methods: Vec<Rc<Function>>,
+    pub class_methods: Vec<Rc<Function>>,
+    pub getters: Vec<Rc<Function>>,
 }
 
 impl Class {
-    pub fn new(name: Token, superclass: Option<Variable>, methods: Vec<Function>) -> Class {
+    pub fn new(
+        name: Token,
+        superclass: Option<Variable>,
+        traits: Vec<Variable>,
+        methods: Vec<Rc<Function>>,
+        class_methods: Vec<Rc<Function>>,
+        getters: Vec<Rc<Function>>,
+    ) -> Class {
         Class {
             name,
             superclass,
+            traits,
             methods,
+            class_methods,
+            getters,
         }
     }
 }
@@ -86,6 +140,23 @@ impl<T> Accept<T> for Class {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Continue {
+    pub keyword: Token,
+}
+
+impl Continue {
+    pub fn new(keyword: Token) -> Continue {
+        Continue { keyword }
+    }
+}
+
+impl<T> Accept<T> for Continue {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_continue_stmt(self)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Expression {
     pub expression: Expr,
@@ -103,20 +174,62 @@ impl<T> Accept<T> for Expression {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct ForIn {
+    pub keyword: Token,
+    pub name: Token,
+    pub iterable: Expr,
+    pub body: Box<Stmt>,
+}
+
+impl ForIn {
+    pub fn new(keyword: Token, name: Token, iterable: Expr, body: Box<Stmt>) -> ForIn {
+        ForIn {
+            keyword,
+            name,
+            iterable,
+            body,
+        }
+    }
+}
+
+impl<T> Accept<T> for ForIn {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_forin_stmt(self)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Function {
     pub name: Token,
     pub params: Vec<Token>,
+    pub defaults: Vec<Option<Expr>>,
+    pub rest: Option<Token>,
     pub body: Vec<Stmt>,
+    pub generator: bool,
 }
 
 impl Function {
-    pub fn new(name: Token, params: Vec<Token>, body: Vec<Stmt>) -> Function {
-        Function { name, params, body }
+    pub fn new(
+        name: Token,
+        params: Vec<Token>,
+        defaults: Vec<Option<Expr>>,
+        rest: Option<Token>,
+        body: Vec<Stmt>,
+        generator: bool,
+    ) -> Function {
+        Function {
+            name,
+            params,
+            defaults,
+            rest,
+            body,
+            generator,
+        }
     }
 }
 
-impl<T> Accept<T> for Function {
+impl<T> Accept<T> for Rc<Function> {
     fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
         visitor.visit_function_stmt(self)
     }
@@ -149,14 +262,64 @@ impl<T> Accept<T> for If {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Match {
+    pub keyword: Token,
+    pub value: Expr,
+    pub arms: Vec<MatchArm>,
+}
+
+impl Match {
+    pub fn new(keyword: Token, value: Expr, arms: Vec<MatchArm>) -> Match {
+        Match {
+            keyword,
+            value,
+            arms,
+        }
+    }
+}
+
+impl<T> Accept<T> for Match {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_match_stmt(self)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MultiAssign {
+    pub targets: Vec<Expr>,
+    pub equals: Token,
+    pub value: Expr,
+}
+
+impl MultiAssign {
+    pub fn new(targets: Vec<Expr>, equals: Token, value: Expr) -> MultiAssign {
+        MultiAssign {
+            targets,
+            equals,
+            value,
+        }
+    }
+}
+
+impl<T> Accept<T> for MultiAssign {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_multiassign_stmt(self)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Print {
+    pub keyword: Token,
     pub expression: Expr,
 }
 
 impl Print {
-    pub fn new(expression: Expr) -> Print {
-        Print { expression }
+    pub fn new(keyword: Token, expression: Expr) -> Print {
+        Print {
+            keyword,
+            expression,
+        }
     }
 }
 
@@ -185,14 +348,39 @@ impl<T> Accept<T> for Return {
 }
 
 #[derive(Clone, Debug)]
-pub struct Var {
+pub struct Trait {
     pub name: Token,
+    pub methods: Vec<Rc<Function>>,
+}
+
+impl Trait {
+    pub fn new(name: Token, methods: Vec<Rc<Function>>) -> Trait {
+        Trait { name, methods }
+    }
+}
+
+impl<T> Accept<T> for Trait {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_trait_stmt(self)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Var {
+    pub names: Vec<Token>,
+    pub pattern: VarPattern,
     pub initializer: Expr,
+    pub constant: bool,
 }
 
 impl Var {
-    pub fn new(name: Token, initializer: Expr) -> Var {
-        Var { name, initializer }
+    pub fn new(names: Vec<Token>, pattern: VarPattern, initializer: Expr, constant: bool) -> Var {
+        Var {
+            names,
+            pattern,
+            initializer,
+            constant,
+        }
     }
 }
 
@@ -204,13 +392,25 @@ impl<T> Accept<T> for Var {
 
 #[derive(Clone, Debug)]
 pub struct While {
+    pub keyword: Token,
     pub condition: Expr,
     pub body: Box<Stmt>,
+    pub increment: Option<Expr>,
 }
 
 impl While {
-    pub fn new(condition: Expr, body: Box<Stmt>) -> Box<While> {
-        Box::new(While { condition, body })
+    pub fn new(
+        keyword: Token,
+        condition: Expr,
+        body: Box<Stmt>,
+        increment: Option<Expr>,
+    ) -> Box<While> {
+        Box::new(While {
+            keyword,
+            condition,
+            body,
+            increment,
+        })
     }
 }
 
@@ -219,3 +419,21 @@ impl<T> Accept<T> for While {
         visitor.visit_while_stmt(self)
     }
 }
+
+#[derive(Clone, Debug)]
+pub struct Yield {
+    pub keyword: Token,
+    pub value: Expr,
+}
+
+impl Yield {
+    pub fn new(keyword: Token, value: Expr) -> Yield {
+        Yield { keyword, value }
+    }
+}
+
+impl<T> Accept<T> for Yield {
+    fn accept(&self, visitor: &mut impl Visitor<T>) -> T {
+        visitor.visit_yield_stmt(self)
+    }
+}
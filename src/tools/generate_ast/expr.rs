@@ -6,24 +6,32 @@ pub const BASE_NAME: &str = "Expr";
 
 pub fn define_ast(output_dir: &str) {
     let types = vec![
-        "Assign; name: Token, value: Box<Expr>".to_string(),
+        "Assign; id: u64, name: Token, operator: Option<Token>, value: Box<Expr>, postfix: bool".to_string(),
         "Binary; left: Box<Expr>, operator: Token, right: Box<Expr>".to_string(),
-        "Call; callee: Box<Expr>, paren: Token, arguments: Vec<Expr>".to_string(),
-        "Get; object: Box<Expr>, name: Token".to_string(),
+        "Call; callee: Box<Expr>, paren: Token, arguments: Vec<Expr>, names: Vec<Token>"
+            .to_string(),
+        "Conditional; condition: Box<Expr>, then_branch: Box<Expr>, else_branch: Box<Expr>"
+            .to_string(),
+        "Get; object: Box<Expr>, name: Token, optional: bool".to_string(),
         "Grouping; expression: Box<Expr>".to_string(),
+        "Index; object: Box<Expr>, bracket: Token, index: Box<Expr>".to_string(),
+        "Lambda; function: Rc<Function>".to_string(),
         "Literal; value: LiteralType".to_string(),
         "Logical; left: Box<Expr>, operator: Token, right: Box<Expr>".to_string(),
-        "Set; object: Box<Expr>, name: Token, value: Box<Expr>".to_string(),
-        "Super; keyword: Token, method: Token".to_string(),
-        "This; keyword: Token".to_string(),
+        "Range; start: Box<Expr>, operator: Token, end: Box<Expr>".to_string(),
+        "Set; object: Box<Expr>, name: Token, operator: Option<Token>, value: Box<Expr>, postfix: bool"
+            .to_string(),
+        "Super; id: u64, keyword: Token, method: Token".to_string(),
+        "This; id: u64, keyword: Token".to_string(),
+        "Tuple; elements: Vec<Expr>".to_string(),
         "Unary; operator: Token, right: Box<Expr>".to_string(),
-        "Variable; name: Token".to_string(),
+        "Variable; id: u64, name: Token".to_string(),
     ];
     let path = format!("{}/{}.rs", output_dir, BASE_NAME.to_lowercase());
     let mut file = File::create(&path).unwrap();
     let content = define_dependency()
         + &utils::define_visitor(types.clone(), BASE_NAME.to_lowercase())
-        + &utils::define_accept(BASE_NAME.to_string())
+        + &utils::define_accept()
         + &utils::define_enum(types.clone(), BASE_NAME.to_string())
         + &utils::define_accept_for_enum(types.clone(), BASE_NAME.to_string())
         + &utils::define_structs(types, BASE_NAME.to_string());
@@ -31,8 +39,11 @@ pub fn define_ast(output_dir: &str) {
 }
 
 fn define_dependency() -> String {
-    "use crate::object::literal_type::LiteralType;
+    "use std::rc::Rc;
+
+     use crate::object::literal_type::LiteralType;
      use crate::scanner::token::Token;
+     use crate::stmt::Function;
 
     "
     .to_string()
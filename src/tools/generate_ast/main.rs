@@ -16,6 +16,6 @@ fn main() {
     fs::create_dir_all(output_dir).unwrap();
     expr::define_ast(output_dir);
     stmt::define_ast(output_dir);
-    Command::new("cargo").args(&["fmt"]).output().unwrap();
+    Command::new("cargo").args(["fmt"]).output().unwrap();
     println!("Generated");
 }
@@ -7,21 +7,29 @@ pub const BASE_NAME: &str = "Stmt";
 pub fn define_ast(output_dir: &str) {
     let types = vec![
         "Block; statements: Vec<Stmt>".to_string(),
-        "Class; name: Token, superclass: Option<Variable>, methods: Vec<Function>".to_string(),
+        "Break; keyword: Token".to_string(),
+        "Class; name: Token, superclass: Option<Variable>, traits: Vec<Variable>, methods: Vec<Rc<Function>>, class_methods: Vec<Rc<Function>>, getters: Vec<Rc<Function>>"
+            .to_string(),
+        "Continue; keyword: Token".to_string(),
         "Expression; expression: Expr".to_string(),
-        "Function; name: Token, params: Vec<Token>, body: Vec<Stmt>".to_string(),
+        "ForIn; keyword: Token, name: Token, iterable: Expr, body: Box<Stmt>".to_string(),
+        "Function; name: Token, params: Vec<Token>, defaults: Vec<Option<Expr>>, rest: Option<Token>, body: Vec<Stmt>, generator: bool".to_string(),
         "If; condition: Expr, then_statement: Box<Stmt>, else_statement: Option<Box<Stmt>>"
             .to_string(),
-        "Print; expression: Expr".to_string(),
+        "Match; keyword: Token, value: Expr, arms: Vec<MatchArm>".to_string(),
+        "MultiAssign; targets: Vec<Expr>, equals: Token, value: Expr".to_string(),
+        "Print; keyword: Token, expression: Expr".to_string(),
         "Return; keyword: Token, value: Expr".to_string(),
-        "Var; name: Token, initializer: Expr".to_string(),
-        "While; condition: Expr, body: Box<Stmt>".to_string(),
+        "Trait; name: Token, methods: Vec<Rc<Function>>".to_string(),
+        "Var; names: Vec<Token>, pattern: VarPattern, initializer: Expr, constant: bool".to_string(),
+        "While; keyword: Token, condition: Expr, body: Box<Stmt>, increment: Option<Expr>".to_string(),
+        "Yield; keyword: Token, value: Expr".to_string(),
     ];
     let path = format!("{}/{}.rs", output_dir, BASE_NAME.to_lowercase());
     let mut file = File::create(&path).unwrap();
     let content = define_dependency()
         + &utils::define_visitor(types.clone(), BASE_NAME.to_lowercase())
-        + &utils::define_accept(BASE_NAME.to_string())
+        + &utils::define_accept()
         + &utils::define_enum(types.clone(), BASE_NAME.to_string())
         + &utils::define_accept_for_enum(types.clone(), BASE_NAME.to_string())
         + &utils::define_structs(types, BASE_NAME.to_string());
@@ -29,7 +37,10 @@ pub fn define_ast(output_dir: &str) {
 }
 
 fn define_dependency() -> String {
-    "use crate::expr::{Expr, Variable};
+    "use std::rc::Rc;
+
+     use crate::expr::{Expr, Variable};
+     use crate::pattern::{MatchArm, VarPattern};
      use crate::scanner::token::Token;
 
     "
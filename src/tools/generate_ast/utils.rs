@@ -1,14 +1,18 @@
-use crate::expr;
-
-const BOX_REQUIRED: [&str; 10] = [
-    "Assign", "Binary", "Call", "Get", "Grouping", "Logical", "Set", "Unary", "If", "While",
+const BOX_REQUIRED: [&str; 11] = [
+    "Assign", "Binary", "Call", "Get", "Grouping", "Index", "Logical", "Set", "Unary", "If",
+    "While",
 ];
 
+/// Nodes that outlive the tree, such as function declarations captured by closures
+const RC_REQUIRED: [&str; 1] = ["Function"];
+
 pub fn define_enum(types: Vec<String>, base_name: String) -> String {
     let fields: String = types.iter().fold(String::new(), |acc, type_string| {
         let struct_name = type_string.split(';').collect::<Vec<&str>>()[0].trim();
         let field = if BOX_REQUIRED.contains(&struct_name) {
             format!("{struct_name}(Box<{struct_name}>),\n")
+        } else if RC_REQUIRED.contains(&struct_name) {
+            format!("{struct_name}(Rc<{struct_name}>),\n")
         } else {
             format!("{struct_name}({struct_name}),\n")
         };
@@ -31,8 +35,9 @@ pub fn define_visitor(types: Vec<String>, base_name: String) -> String {
         let struct_name_and_fields: Vec<&str> = type_string.split(';').collect();
         let struct_name = struct_name_and_fields[0].trim();
         let lowered_struct_name = struct_name.to_lowercase();
+        let node_type = node_type(struct_name);
         acc + &format!(
-            "fn visit_{lowered_struct_name}_{base_name}(&mut self, {base_name}: &{struct_name}) -> T;\n"
+            "fn visit_{lowered_struct_name}_{base_name}(&mut self, {base_name}: &{node_type}) -> T;\n"
         )
     });
     format!(
@@ -44,15 +49,13 @@ pub fn define_visitor(types: Vec<String>, base_name: String) -> String {
     )
 }
 
-pub fn define_accept(base_name: String) -> String {
-    let first_param = get_accept_first_param(base_name);
-    format!(
-        "pub trait Accept<T> {{
-             fn accept({first_param}, visitor: &mut impl Visitor<T>) -> T;
-         }}
+pub fn define_accept() -> String {
+    "pub trait Accept<T> {
+         fn accept(&self, visitor: &mut impl Visitor<T>) -> T;
+     }
 
     "
-    )
+    .to_string()
 }
 
 pub fn define_struct(struct_name: String, fields: String) -> String {
@@ -123,7 +126,7 @@ pub fn define_structs(types: Vec<String>, base_name: String) -> String {
         let new_function = define_new_function(struct_name.clone(), fields);
         let lowered_struct_name = struct_name.to_lowercase();
         let lowered_base_name = base_name.to_lowercase();
-        let first_param = get_accept_first_param(base_name.clone());
+        let node_type = node_type(&struct_name);
         acc + &format!(
             "
             {struct_in_string}
@@ -132,8 +135,8 @@ pub fn define_structs(types: Vec<String>, base_name: String) -> String {
                 {new_function}
             }}
 
-            impl<T> Accept<T> for {struct_name} {{
-                fn accept({first_param}, visitor: &mut impl Visitor<T>) -> T {{
+            impl<T> Accept<T> for {node_type} {{
+                fn accept(&self, visitor: &mut impl Visitor<T>) -> T {{
                     visitor.visit_{lowered_struct_name}_{lowered_base_name}(self)
                 }}
             }}
@@ -148,11 +151,10 @@ pub fn define_accept_for_enum(types: Vec<String>, base_name: String) -> String {
         let struct_name = type_string.split(';').collect::<Vec<&str>>()[0].trim();
         acc + &format!("{base_name}::{struct_name}(e) => e.accept(visitor),\n")
     });
-    let first_param = get_accept_first_param(base_name.clone());
     format!(
         "
         impl<T> Accept<T> for {base_name} {{
-            fn accept({first_param}, visitor: &mut impl Visitor<T>) -> T {{
+            fn accept(&self, visitor: &mut impl Visitor<T>) -> T {{
                 match self {{
                     {accept}
                 }}
@@ -163,10 +165,11 @@ pub fn define_accept_for_enum(types: Vec<String>, base_name: String) -> String {
     )
 }
 
-fn get_accept_first_param(base_name: String) -> String {
-    match base_name.as_str() {
-        expr::BASE_NAME => "&mut self",
-        _ => "&self",
+/// The type visitors get for a node, shared nodes are visited through their `Rc`
+fn node_type(struct_name: &str) -> String {
+    if RC_REQUIRED.contains(&struct_name) {
+        format!("Rc<{struct_name}>")
+    } else {
+        struct_name.to_string()
     }
-    .to_string()
 }